@@ -0,0 +1,235 @@
+use crate::game_states::game_state_builder::{GameStateBuilder, GameStateBuilderBuilder};
+use crate::game_states::play_state::controls::{
+    get_button_name, get_command_name, ControlsConfig, COMMANDS, CONTROLS_PATH,
+};
+use crate::game_states::play_state::gamepads;
+use crate::game_states::play_state::players::PlayerId;
+use crate::game_states::state_manager::{GameState, StateTransition};
+use graphics::character::CharacterCache;
+use graphics::Transformed;
+use opengl_graphics::{GlGraphics, GlyphCache, TextureSettings};
+use piston::input::{Button, ButtonEvent, ButtonState, Event, Key, RenderEvent};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+const FONT_PATH: &str = "assets/fonts/verdana.ttf";
+const TITLE_FONT_SIZE: u32 = 24;
+const BINDING_FONT_SIZE: u32 = 16;
+const HINT_FONT_SIZE: u32 = 14;
+const LINE_HEIGHT: f64 = 26.0;
+
+/// Lets the players rebind their controls and writes them back to the controls file.
+/// Closing it returns to the screen below or, when it is shown before the first round,
+/// goes on to `next_state`.
+pub struct ControlsState {
+    gl: Rc<RefCell<GlGraphics>>,
+    glyph_cache: GlyphCache<'static>,
+    pending_transitions: Arc<RwLock<VecDeque<StateTransition>>>,
+    controls_config: Arc<RwLock<ControlsConfig>>,
+    player_ids: Vec<PlayerId>,
+    selected_player: usize,
+    selected_command: usize,
+    is_waiting_for_button: bool,
+    message: String,
+    next_state: Option<GameStateBuilder>,
+}
+
+impl ControlsState {
+    pub fn build(next_state: Option<GameStateBuilder>) -> GameStateBuilder {
+        GameStateBuilderBuilder::new().build(move |resources| {
+            let controls_state = ControlsState {
+                gl: resources.gl.clone(),
+                glyph_cache: GlyphCache::new(FONT_PATH, (), TextureSettings::new()).unwrap(),
+                pending_transitions: resources.pending_transitions.clone(),
+                controls_config: resources.controls_config.clone(),
+                player_ids: resources
+                    .player_setup
+                    .read()
+                    .unwrap()
+                    .get_player_ids()
+                    .to_vec(),
+                selected_player: 0,
+                selected_command: 0,
+                is_waiting_for_button: false,
+                message: String::new(),
+                next_state,
+            };
+
+            Box::new(controls_state)
+        })
+    }
+
+    fn get_lines(&self) -> Vec<(String, u32)> {
+        let player_id = self.player_ids[self.selected_player];
+        let controls_config = self.controls_config.read().unwrap();
+        let profile = controls_config.get_profile(player_id);
+
+        let mut lines = vec![(
+            format!("Player {} controls", u32::from(player_id)),
+            TITLE_FONT_SIZE,
+        )];
+
+        lines.extend(COMMANDS.iter().enumerate().map(|(index, command)| {
+            let button_name = if index == self.selected_command && self.is_waiting_for_button {
+                "...".to_string()
+            } else {
+                get_button_name(profile.get_button(*command))
+            };
+            let cursor = if index == self.selected_command {
+                "> "
+            } else {
+                ""
+            };
+
+            (
+                format!("{}{}: {}", cursor, get_command_name(*command), button_name),
+                BINDING_FONT_SIZE,
+            )
+        }));
+
+        lines.push((self.message.clone(), HINT_FONT_SIZE));
+        lines.push((self.get_hint().to_string(), HINT_FONT_SIZE));
+
+        lines
+    }
+
+    fn get_hint(&self) -> &str {
+        if self.is_waiting_for_button {
+            "Press a key or Escape to cancel"
+        } else {
+            "Arrows to select, Enter to rebind, Escape to save"
+        }
+    }
+
+    fn bind(&mut self, button: Button) {
+        let player_id = self.player_ids[self.selected_player];
+        let command = COMMANDS[self.selected_command];
+
+        self.message = match self.controls_config.write().unwrap().bind(
+            &self.player_ids,
+            player_id,
+            command,
+            button,
+        ) {
+            Ok(()) => String::new(),
+            Err(error) => error,
+        };
+    }
+
+    fn save_and_close(&mut self) {
+        match self
+            .controls_config
+            .read()
+            .unwrap()
+            .save(Path::new(CONTROLS_PATH))
+        {
+            Ok(()) => {
+                let transition = match self.next_state.take() {
+                    Some(next_state) => StateTransition::Switch(next_state),
+                    None => StateTransition::Pop,
+                };
+                self.pending_transitions
+                    .write()
+                    .unwrap()
+                    .push_back(transition);
+            }
+            Err(error) => self.message = error,
+        }
+    }
+
+    fn press(&mut self, button: Button) {
+        match (self.is_waiting_for_button, button) {
+            (true, Button::Keyboard(Key::Escape)) => self.is_waiting_for_button = false,
+            (true, Button::Keyboard(key)) => {
+                self.bind(Button::Keyboard(key));
+                self.is_waiting_for_button = false;
+            }
+            (false, Button::Keyboard(Key::Return)) => {
+                self.is_waiting_for_button = true;
+                self.message.clear();
+            }
+            (false, Button::Keyboard(Key::Escape)) => self.save_and_close(),
+            (false, Button::Keyboard(key)) => self.select(key),
+            _ => {}
+        }
+    }
+
+    fn select(&mut self, key: Key) {
+        let player_count = self.player_ids.len();
+        let command_count = COMMANDS.len();
+
+        match key {
+            Key::Left => {
+                self.selected_player = (self.selected_player + player_count - 1) % player_count
+            }
+            Key::Right => self.selected_player = (self.selected_player + 1) % player_count,
+            Key::Up => {
+                self.selected_command = (self.selected_command + command_count - 1) % command_count
+            }
+            Key::Down => self.selected_command = (self.selected_command + 1) % command_count,
+            _ => return,
+        }
+
+        self.message.clear();
+    }
+}
+
+impl GameState for ControlsState {
+    fn execute(&mut self, event: Event) -> bool {
+        if let Some(render_args) = event.render_args() {
+            let lines = self.get_lines();
+
+            let graphics = &mut (*self.gl.borrow_mut());
+            let context = graphics.draw_begin(render_args.viewport());
+            let [width, height] = render_args.window_size;
+
+            graphics::rectangle(
+                [0.0, 0.0, 0.0, 0.8],
+                [0.0, 0.0, width, height],
+                context.transform,
+                graphics,
+            );
+
+            let top = (height - lines.len() as f64 * LINE_HEIGHT) / 2.0;
+            for (index, (text, font_size)) in lines.iter().enumerate() {
+                let text_width = self.glyph_cache.width(*font_size, text).unwrap();
+
+                graphics::text(
+                    [1.0; 4],
+                    *font_size,
+                    text,
+                    &mut self.glyph_cache,
+                    context
+                        .transform
+                        .trans((width - text_width) / 2.0, top + index as f64 * LINE_HEIGHT),
+                    graphics,
+                )
+                .unwrap();
+            }
+
+            graphics.draw_end();
+        } else if let Some(button_args) = event.button_args() {
+            if button_args.state == ButtonState::Press {
+                self.press(button_args.button);
+            }
+        } else if let Some(commands) = gamepads::get_commands(&event) {
+            // gamepads navigate the screen, but only keys can be bound,
+            // so a gamepad can only cancel the wait for a key
+            let key = commands
+                .iter()
+                .filter_map(gamepads::get_menu_key)
+                .find(|key| !self.is_waiting_for_button || *key == Key::Escape);
+
+            // closing the screen is applied after this event, so only the first press counts
+            if let Some(key) = key {
+                self.press(Button::Keyboard(key));
+            }
+        }
+
+        // the screens below must not react to the buttons that are being bound
+        false
+    }
+}
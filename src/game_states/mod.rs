@@ -1,3 +1,5 @@
+pub mod controls_state;
 pub mod game_state_builder;
 pub mod play_state;
+pub mod results_state;
 pub mod state_manager;
@@ -1,11 +1,23 @@
+mod bombs;
+mod collisions;
 mod components;
+pub mod controls;
+mod diseases;
+pub mod gamepads;
+mod grid;
+mod input;
 mod map;
 mod object_groups;
-mod players;
+pub mod players;
+mod power_ups;
+pub mod replays;
+pub mod round;
+mod sudden_death;
 mod systems;
 
 use crate::game_states::game_state_builder::{GameStateBuilder, GameStateBuilderBuilder};
-use crate::game_states::state_manager::GameState;
+use crate::game_states::results_state::ResultsState;
+use crate::game_states::state_manager::{GameState, StateTransition};
 use crate::tiles::tilemap::Tilemap;
 use crate::tiles::tileset::Tileset;
 use legion::schedule::Schedule;
@@ -16,10 +28,35 @@ use nphysics2d::force_generator::DefaultForceGeneratorSet;
 use nphysics2d::joint::DefaultJointConstraintSet;
 use nphysics2d::object::{DefaultBodySet, DefaultColliderSet};
 use nphysics2d::world::{DefaultGeometricalWorld, DefaultMechanicalWorld};
-use piston::input::Event;
-use players::{PlayerId, Players};
+use object_groups::ArenaObjectGroup;
+use piston::input::{Event, UpdateEvent};
+use players::{PlayerId, PlayerSetup, PlayerStats, Players};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use replays::Replays;
+use round::{MatchScore, Round};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 
 const TILEMAP_ID: &str = "ashlands";
+const TILEMAP_PATH: &str = "assets/textures/arena_tiles/ashlands.tmx";
+
+/// Reads only the objects of the map, so a map without a spawn for one of the players
+/// is rejected before the first round is built
+pub fn check_player_spawns(player_setup: &PlayerSetup) -> Result<(), String> {
+    let tilemap = tiled::parse_file(Path::new(TILEMAP_PATH))
+        .map_err(|error| format!("Cannot read {}: {}", TILEMAP_PATH, error))?;
+    let spawns = tilemap
+        .object_groups
+        .into_iter()
+        .filter(|group| group.name == ArenaObjectGroup::PlayerSpawns.as_str())
+        .flat_map(|group| group.objects)
+        .collect::<Vec<_>>();
+
+    map::check_player_spawns(&spawns, player_setup.get_player_ids())
+        .map_err(|error| format!("{}: {}", TILEMAP_PATH, error))
+}
 
 pub struct PhysicsWorld<N: RealField = f64> {
     mechanical_world: DefaultMechanicalWorld<N>,
@@ -46,23 +83,30 @@ impl PhysicsWorld<f64> {
 pub struct PlayState {
     world: World,
     schedule: Schedule,
-    map: Map,
     players: Players,
+    pending_transitions: Arc<RwLock<VecDeque<StateTransition>>>,
+    match_score: Arc<RwLock<MatchScore>>,
+    replays: Arc<RwLock<Replays>>,
+    input_layer: input::InputLayer,
+    is_round_over: bool,
 }
 
 impl PlayState {
     pub fn build() -> GameStateBuilder {
-        GameStateBuilderBuilder::new()
-            .load_asset::<Tilemap>("assets/textures/arena_tiles/ashlands.tmx", TILEMAP_ID)
-            .load_asset::<Tileset>(
-                "assets/textures/player/player1.xml",
-                PlayerId::Player1.as_str(),
-            )
+        PlayerId::ALL
+            .iter()
+            .fold(GameStateBuilderBuilder::new(), |builder, player_id| {
+                builder.load_asset::<Tileset>(
+                    &format!("assets/textures/player/{}.xml", player_id.as_str()),
+                    player_id.as_str(),
+                )
+            })
+            .load_asset::<Tilemap>(TILEMAP_PATH, TILEMAP_ID)
+            .load_asset::<Tileset>("assets/textures/bomb/bomb.xml", "bomb")
             .load_asset::<Tileset>(
-                "assets/textures/player/player2.xml",
-                PlayerId::Player2.as_str(),
+                "assets/textures/power_ups/power_ups.xml",
+                power_ups::POWER_UPS_TILESET_ID,
             )
-            .load_asset::<Tileset>("assets/textures/bomb/bomb.xml", "bomb")
             .build(|resources| {
                 let tilemap = resources
                     .asset_storage
@@ -77,31 +121,68 @@ impl PlayState {
                     .resources
                     .insert(components::AssetStorage(resources.asset_storage.clone()));
                 world.resources.insert(components::Tilemap(tilemap.clone()));
+                world
+                    .resources
+                    .insert(power_ups::PowerUpDrops::from_tilemap(&tilemap));
+                world.resources.insert(grid::ArenaGrid::new(&tilemap));
+                world.resources.insert(PlayerStats::default());
+                world.resources.insert(input::PlayerCommands::default());
+                world
+                    .resources
+                    .insert(collisions::PlayerCollisionPolicy::from_tilemap(&tilemap));
+                world.resources.insert(Round::new());
+                world
+                    .resources
+                    .insert(sudden_death::SuddenDeath::from_tilemap(&tilemap));
 
                 let mut map = Map::new(tilemap.clone());
                 map.create_tilemap_entities(&mut world, &mut physics_world);
-                map.create_soft_blocks(&mut world, &mut physics_world);
+                let seed = resources.replays.write().unwrap().start_round();
+                let mut rng = StdRng::seed_from_u64(seed);
+                map.create_soft_blocks(&mut world, &mut physics_world, &mut rng);
+                world.resources.insert(components::SeededRng(rng));
+
+                let player_setup = resources.player_setup.read().unwrap().clone();
+                let input_sources: Vec<Box<dyn input::InputSource>> =
+                    if resources.replays.read().unwrap().is_playing() {
+                        vec![Box::new(replays::ReplayInput::new(
+                            resources.replays.clone(),
+                        ))]
+                    } else {
+                        vec![
+                            Box::new(input::KeyboardInput::new(
+                                &resources.controls_config.read().unwrap(),
+                                player_setup.get_player_ids(),
+                            )),
+                            Box::new(input::GamepadInput),
+                        ]
+                    };
 
                 let mut players = Players::new();
                 let player_spawns = map.get_player_spawns();
-                players.create_player(
-                    PlayerId::Player1,
-                    &player_spawns,
-                    &resources.asset_storage.read().unwrap(),
-                    &tilemap,
-                    &mut world,
-                    &mut physics_world,
-                );
-                players.create_player(
-                    PlayerId::Player2,
-                    &player_spawns,
-                    &resources.asset_storage.read().unwrap(),
-                    &tilemap,
-                    &mut world,
-                    &mut physics_world,
-                );
+                for player_id in player_setup.get_player_ids() {
+                    players.create_player(
+                        *player_id,
+                        player_spawns[player_id],
+                        &resources.asset_storage.read().unwrap(),
+                        &mut world,
+                        &mut physics_world,
+                    );
+                }
 
                 world.resources.insert(physics_world);
+
+                let tile_animations = map.tile_animations.clone();
+                let map = Arc::new(RwLock::new(map));
+                world.resources.insert(components::Map(map));
+
+                {
+                    let mut match_score = resources.match_score.write().unwrap();
+                    for player_id in player_setup.get_player_ids() {
+                        match_score.add_player(*player_id);
+                    }
+                }
+
                 let play_state = PlayState {
                     world,
                     schedule: Schedule::builder()
@@ -110,22 +191,40 @@ impl PlayState {
                         .flush()
                         .add_system(systems::create_bomb_spawn_command_status_system())
                         .add_system(systems::create_spawn_bomb_system())
+                        .add_system(systems::create_line_bomb_window_system())
+                        .add_system(systems::create_remote_detonation_system())
                         .add_system(systems::create_update_bomb_collision_status_system())
+                        .add_system(systems::create_slide_bomb_system())
+                        .add_system(systems::create_fly_bomb_system())
+                        .add_system(systems::create_fuse_system())
+                        .add_system(systems::create_detonation_system())
+                        .add_system(systems::create_lifetime_system())
+                        .add_system(systems::create_pick_up_power_up_system())
+                        .add_system(systems::create_burn_player_system())
+                        .add_system(systems::create_dying_player_system())
+                        .add_system(systems::create_invulnerability_system())
+                        .add_system(systems::create_disease_system())
+                        .add_system(systems::create_spread_disease_system())
+                        .add_system(systems::create_sudden_death_system())
+                        .add_system(systems::create_round_system())
                         .add_system(systems::create_turn_player_system())
                         .add_system(systems::create_move_player_system())
                         .add_system(systems::create_update_physics_world_system())
+                        .add_system(systems::create_update_grid_system())
                         .add_system(systems::create_clear_collision_events_system())
-                        .add_system(systems::create_animation_system(
-                            map.tile_animations.clone(),
-                        ))
+                        .add_system(systems::create_animation_system(tile_animations))
                         .add_thread_local(systems::create_draw_system(
                             resources.gl.clone(),
-                            tilemap.tiles.len() + 1,
+                            (tilemap.tiles.len() + 1).max(bombs::AIRBORNE_LAYER + 1),
                         ))
                         .add_thread_local(systems::create_draw_hit_box_system(resources.gl.clone()))
                         .build(),
-                    map,
                     players,
+                    pending_transitions: resources.pending_transitions.clone(),
+                    match_score: resources.match_score.clone(),
+                    replays: resources.replays.clone(),
+                    input_layer: input::InputLayer::new(input_sources),
+                    is_round_over: false,
                 };
 
                 Box::new(play_state)
@@ -135,8 +234,41 @@ impl PlayState {
 
 impl GameState for PlayState {
     fn execute(&mut self, event: Event) -> bool {
+        let player_commands = self.input_layer.handle_event(&event);
+        if event.update_args().is_some() {
+            self.replays.write().unwrap().add_tick(&player_commands);
+        }
+        self.world.resources.insert(player_commands);
         self.world.resources.insert(event);
         self.schedule.execute(&mut self.world);
+
+        let world = &self.world;
+        self.players
+            .players
+            .retain(|player| world.is_alive(*player));
+
+        if !self.is_round_over {
+            if let Some(outcome) = world.resources.get::<Round>().unwrap().get_outcome() {
+                self.is_round_over = true;
+                let is_match_over = {
+                    let mut match_score = self.match_score.write().unwrap();
+                    match_score.add_round_outcome(outcome);
+                    match_score.get_match_winner().is_some()
+                };
+                let message = self
+                    .replays
+                    .write()
+                    .unwrap()
+                    .end_round(is_match_over)
+                    .err()
+                    .unwrap_or_default();
+                self.pending_transitions
+                    .write()
+                    .unwrap()
+                    .push_back(StateTransition::Push(ResultsState::build(outcome, message)));
+            }
+        }
+
         true
     }
 }
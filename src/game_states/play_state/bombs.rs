@@ -0,0 +1,410 @@
+use crate::game_states::play_state::players::Direction;
+use crate::tiles::tilemap::Tilemap;
+use crate::tiles::tileset::{TileId, TilePosition, Tileset};
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use tiled::PropertyValue;
+
+const DEFAULT_FUSE_DURATION: f64 = 3.0;
+pub const FLAME_DURATION: f64 = 0.5;
+/// In tiles per second
+pub const BOMB_SLIDE_SPEED: f64 = 6.0;
+/// In tiles
+pub const THROW_DISTANCE: u32 = 3;
+/// In tiles per second
+pub const THROW_SPEED: f64 = 8.0;
+/// How often a thrown bomb bounces on over occupied tiles before it drops
+/// onto the nearest free tile instead
+pub const MAX_THROW_BOUNCES: u32 = 4;
+/// Thrown bombs are drawn above the players, which are on `Layer(2)`
+pub const AIRBORNE_LAYER: usize = 3;
+/// How quickly the bomb button has to be pressed again to drop a line bomb
+pub const LINE_BOMB_DOUBLE_PRESS_DURATION: f64 = 0.3;
+
+pub enum BombProperties {
+    BombType,
+    FuseDuration,
+    Flame,
+    /// The flames go through soft blocks instead of stopping at them
+    Pierce,
+    /// The blast range is the maximum a player can have
+    MaxRange,
+    /// No other bombs can be placed while the bomb lies in the arena
+    UsesAllCapacity,
+    /// Colors the bomb, so that players can tell the bomb types apart
+    Tint,
+}
+
+impl BombProperties {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BombProperties::BombType => "bomb_type",
+            BombProperties::FuseDuration => "fuse_duration",
+            BombProperties::Flame => "flame",
+            BombProperties::Pierce => "pierce",
+            BombProperties::MaxRange => "max_range",
+            BombProperties::UsesAllCapacity => "uses_all_capacity",
+            BombProperties::Tint => "tint",
+        }
+    }
+}
+
+/// Selects the bomb tile a player places. Its behaviour is configured
+/// through the properties of that tile.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum BombType {
+    Normal,
+    Pierce,
+    Power,
+}
+
+impl BombType {
+    pub fn get_tile_id(&self, tileset: &Tileset) -> Option<TileId> {
+        tileset
+            .properties
+            .iter()
+            .find(|(_tile_id, properties)| {
+                match properties.get(BombProperties::BombType.as_str()) {
+                    Some(PropertyValue::StringValue(bomb_type)) => {
+                        self.as_str() == bomb_type.as_str()
+                    }
+                    _ => false,
+                }
+            })
+            .map(|(tile_id, _)| *tile_id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            BombType::Normal => "normal",
+            BombType::Pierce => "pierce",
+            BombType::Power => "power",
+        }
+    }
+}
+
+pub fn get_fuse_duration(tileset: &Tileset, tile_id: TileId) -> f64 {
+    tileset
+        .properties
+        .get(&tile_id)
+        .and_then(|properties| properties.get(BombProperties::FuseDuration.as_str()))
+        .and_then(|property_value| match property_value {
+            PropertyValue::FloatValue(fuse_duration) => Some(*fuse_duration as f64),
+            _ => None,
+        })
+        .unwrap_or(DEFAULT_FUSE_DURATION)
+}
+
+pub fn has_property(tileset: &Tileset, tile_id: TileId, property: BombProperties) -> bool {
+    match tileset
+        .properties
+        .get(&tile_id)
+        .and_then(|properties| properties.get(property.as_str()))
+    {
+        Some(PropertyValue::BoolValue(value)) => *value,
+        _ => false,
+    }
+}
+
+/// Reads a color property written by Tiled as `#AARRGGBB`, ignoring the alpha
+pub fn get_tint(tileset: &Tileset, tile_id: TileId) -> Option<[f32; 3]> {
+    match tileset
+        .properties
+        .get(&tile_id)
+        .and_then(|properties| properties.get(BombProperties::Tint.as_str()))
+    {
+        Some(PropertyValue::ColorValue(color)) => {
+            let get_channel = |shift: u32| ((color >> shift) & 0xff) as f32 / 255.0;
+            Some([get_channel(16), get_channel(8), get_channel(0)])
+        }
+        _ => None,
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum FlamePart {
+    Center,
+    /// Part of the blast which spreads into the direction
+    Arm(Direction),
+    /// The last tile the blast reaches in the direction
+    End(Direction),
+}
+
+impl FlamePart {
+    pub fn get_tile_id(&self, tileset: &Tileset) -> Option<TileId> {
+        tileset
+            .properties
+            .iter()
+            .find(
+                |(_tile_id, properties)| match properties.get(BombProperties::Flame.as_str()) {
+                    Some(PropertyValue::StringValue(flame)) => self.as_str() == flame.as_str(),
+                    _ => false,
+                },
+            )
+            .map(|(tile_id, _)| *tile_id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            FlamePart::Center => "center",
+            FlamePart::Arm(_) => "arm",
+            FlamePart::End(_) => "end",
+        }
+    }
+
+    /// The flame textures burn upwards, so they are rotated clockwise
+    /// until they point into the direction of the blast.
+    pub fn get_rotation(&self) -> f64 {
+        let direction = match self {
+            FlamePart::Center => return 0.0,
+            FlamePart::Arm(direction) | FlamePart::End(direction) => direction,
+        };
+
+        match direction {
+            Direction::Up => 0.0,
+            Direction::Right => 90.0,
+            Direction::Down => 180.0,
+            Direction::Left => 270.0,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum BlastObstacle {
+    /// Stops the blast before it reaches the tile
+    Indestructible,
+    /// Stops the blast on the tile
+    Destructible,
+}
+
+/// Spreads a blast from `origin` into all four directions up to `range` tiles.
+/// Returns the flames in a fixed order: center first, then up, down, left and right.
+pub fn propagate_blast(
+    tilemap: &Tilemap,
+    origin: TilePosition,
+    range: u32,
+    get_obstacle: impl Fn(TilePosition) -> Option<BlastObstacle>,
+) -> Vec<(TilePosition, FlamePart)> {
+    let mut flames = vec![(origin, FlamePart::Center)];
+
+    for direction in &[
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ] {
+        let mut arm = vec![];
+        let mut position = origin;
+
+        for _ in 0..range {
+            position =
+                match tilemap.get_neighbour_tile_position(position, direction.get_tile_offset()) {
+                    Some(position) => position,
+                    None => break,
+                };
+
+            match get_obstacle(position) {
+                Some(BlastObstacle::Indestructible) => break,
+                Some(BlastObstacle::Destructible) => {
+                    arm.push((position, FlamePart::Arm(*direction)));
+                    break;
+                }
+                None => arm.push((position, FlamePart::Arm(*direction))),
+            }
+        }
+
+        if let Some((_, flame_part)) = arm.last_mut() {
+            *flame_part = FlamePart::End(*direction);
+        }
+
+        flames.extend(arm);
+    }
+
+    flames
+}
+
+/// Searches the arena outwards from `origin`, one tile step at a time, for the nearest tile
+/// which is free. Ties are broken in the order up, down, left and right.
+pub fn find_nearest_free_position(
+    tilemap: &Tilemap,
+    origin: TilePosition,
+    is_free: impl Fn(TilePosition) -> bool,
+) -> Option<TilePosition> {
+    let mut pending_positions = VecDeque::new();
+    let mut visited_positions = HashSet::new();
+    pending_positions.push_back(origin);
+    visited_positions.insert(origin);
+
+    while let Some(position) = pending_positions.pop_front() {
+        if is_free(position) {
+            return Some(position);
+        }
+
+        for direction in &[
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ] {
+            if let Some(neighbour) =
+                tilemap.get_neighbour_tile_position(position, direction.get_tile_offset())
+            {
+                if visited_positions.insert(neighbour) {
+                    pending_positions.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Detonates the bombs which went off in the order of their tiles, then every bomb they hit
+/// in the order in which it was hit, so a chain reaction always resolves the same way.
+/// A bomb which is hit more than once only detonates once.
+/// Returns the bombs in the order in which they detonated.
+pub fn chain_detonations<T: Copy + Eq + Hash>(
+    mut bombs: Vec<(TilePosition, T)>,
+    mut detonate: impl FnMut(T) -> Vec<T>,
+) -> Vec<T> {
+    bombs.sort_by_key(|(position, _)| *position);
+
+    let mut pending_bombs = bombs
+        .into_iter()
+        .map(|(_, bomb)| bomb)
+        .collect::<VecDeque<T>>();
+    let mut detonated_bombs = HashSet::new();
+    let mut detonation_order = vec![];
+
+    while let Some(bomb) = pending_bombs.pop_front() {
+        if !detonated_bombs.insert(bomb) {
+            continue;
+        }
+
+        detonation_order.push(bomb);
+        pending_bombs.extend(detonate(bomb));
+    }
+
+    detonation_order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn create_tilemap(hard_blocks: &[TilePosition]) -> Tilemap {
+        let hard_block_tile_id = 1;
+
+        let mut tileset = Tileset::default();
+        tileset
+            .hit_boxes
+            .insert(hard_block_tile_id, [0.0, 0.0, 32.0, 32.0]);
+
+        let mut tilemap = Tilemap::with_size(5, 5);
+        tilemap.tiles = vec![hard_blocks
+            .iter()
+            .map(|position| (*position, hard_block_tile_id))
+            .collect()];
+        tilemap.tileset = Arc::new(tileset);
+
+        tilemap
+    }
+
+    #[test]
+    fn test_propagate_blast() {
+        let tilemap = create_tilemap(&[[64, 32]]);
+        let soft_block = [96, 64];
+
+        let flames = propagate_blast(&tilemap, [64, 64], 3, |position| {
+            if tilemap.has_hit_box(position) {
+                Some(BlastObstacle::Indestructible)
+            } else if position == soft_block {
+                Some(BlastObstacle::Destructible)
+            } else {
+                None
+            }
+        });
+
+        assert_eq!(
+            flames,
+            vec![
+                ([64, 64], FlamePart::Center),
+                ([64, 96], FlamePart::Arm(Direction::Down)),
+                ([64, 128], FlamePart::End(Direction::Down)),
+                ([32, 64], FlamePart::Arm(Direction::Left)),
+                ([0, 64], FlamePart::End(Direction::Left)),
+                ([96, 64], FlamePart::End(Direction::Right)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flame_rotation() {
+        assert_eq!(FlamePart::Center.get_rotation(), 0.0);
+        assert_eq!(FlamePart::End(Direction::Up).get_rotation(), 0.0);
+        assert_eq!(FlamePart::Arm(Direction::Right).get_rotation(), 90.0);
+        assert_eq!(FlamePart::End(Direction::Down).get_rotation(), 180.0);
+        assert_eq!(FlamePart::Arm(Direction::Left).get_rotation(), 270.0);
+    }
+
+    #[test]
+    fn test_find_nearest_free_position() {
+        let tilemap = create_tilemap(&[]);
+        let occupied = [[64, 64], [64, 32], [64, 96], [32, 64]];
+        let is_free = |position| !occupied.contains(&position);
+
+        assert_eq!(
+            find_nearest_free_position(&tilemap, [64, 64], is_free),
+            Some([96, 64])
+        );
+        assert_eq!(
+            find_nearest_free_position(&tilemap, [0, 0], |position| position == [128, 128]),
+            Some([128, 128])
+        );
+        assert_eq!(
+            find_nearest_free_position(&tilemap, [0, 0], |_| false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_tint() {
+        let mut tileset = Tileset::default();
+        tileset.properties.insert(
+            40,
+            vec![(
+                BombProperties::Tint.as_str().to_string(),
+                PropertyValue::ColorValue(0xff_ff_80_00),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(get_tint(&tileset, 40), Some([1.0, 128.0 / 255.0, 0.0]));
+        assert_eq!(get_tint(&tileset, 0), None);
+    }
+
+    #[test]
+    fn test_chain_detonations() {
+        let hit_bombs: HashMap<char, Vec<char>> = vec![
+            ('a', vec!['b', 'c']),
+            ('b', vec!['c']),
+            ('c', vec!['b', 'd']),
+            ('d', vec![]),
+            ('e', vec!['a']),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut detonations = vec![];
+        let detonation_order = chain_detonations(vec![([96, 96], 'e'), ([32, 32], 'a')], |bomb| {
+            detonations.push(bomb);
+            hit_bombs[&bomb].clone()
+        });
+
+        assert_eq!(detonation_order, vec!['a', 'e', 'b', 'c', 'd']);
+        assert_eq!(detonations, detonation_order);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::game_states::play_state::components::EntityType;
+use crate::tiles::tilemap::Tilemap;
+use ncollide2d::pipeline::CollisionGroups;
+use tiled::PropertyValue;
+
+const PLAYER_GROUP: usize = 0;
+const BOMB_GROUP: usize = 1;
+const SOFT_BLOCK_GROUP: usize = 2;
+const HARD_BLOCK_GROUP: usize = 3;
+const FLAME_GROUP: usize = 4;
+const POWER_UP_GROUP: usize = 5;
+/// Sensors of players, which only report players touching each other
+const PLAYER_TOUCH_GROUP: usize = 6;
+
+pub enum CollisionProperties {
+    PlayerCollisions,
+}
+
+impl CollisionProperties {
+    pub fn as_str(&self) -> &str {
+        match self {
+            CollisionProperties::PlayerCollisions => "player_collisions",
+        }
+    }
+}
+
+/// Whether players can walk through each other, which is configured in the map properties.
+/// Player bodies never push each other around, so blocking is resolved on the arena grid.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum PlayerCollisionPolicy {
+    Block,
+    PassThrough,
+}
+
+impl PlayerCollisionPolicy {
+    pub fn from_tilemap(tilemap: &Tilemap) -> PlayerCollisionPolicy {
+        match tilemap
+            .properties
+            .get(CollisionProperties::PlayerCollisions.as_str())
+        {
+            Some(PropertyValue::StringValue(policy)) => {
+                PlayerCollisionPolicy::from(policy.as_str())
+            }
+            _ => PlayerCollisionPolicy::PassThrough,
+        }
+    }
+}
+
+impl From<&str> for PlayerCollisionPolicy {
+    fn from(policy: &str) -> Self {
+        match policy {
+            "block" => PlayerCollisionPolicy::Block,
+            "pass_through" => PlayerCollisionPolicy::PassThrough,
+            _ => panic!("Cannot create PlayerCollisionPolicy from {}", policy),
+        }
+    }
+}
+
+fn get_collision_group(entity_type: EntityType) -> usize {
+    match entity_type {
+        EntityType::Player => PLAYER_GROUP,
+        EntityType::Bomb => BOMB_GROUP,
+        EntityType::SoftBlock => SOFT_BLOCK_GROUP,
+        EntityType::HardBlock => HARD_BLOCK_GROUP,
+        EntityType::Flame => FLAME_GROUP,
+        EntityType::PowerUp => POWER_UP_GROUP,
+    }
+}
+
+/// Players collide with everything but other players, while everything else only collides with players.
+pub fn get_collision_groups(entity_type: EntityType) -> CollisionGroups {
+    let whitelist = match entity_type {
+        EntityType::Player => vec![
+            BOMB_GROUP,
+            SOFT_BLOCK_GROUP,
+            HARD_BLOCK_GROUP,
+            POWER_UP_GROUP,
+        ],
+        EntityType::Flame => vec![],
+        _ => vec![PLAYER_GROUP],
+    };
+
+    CollisionGroups::new()
+        .with_membership(&[get_collision_group(entity_type)])
+        .with_whitelist(&whitelist)
+}
+
+pub fn get_player_touch_collision_groups() -> CollisionGroups {
+    CollisionGroups::new()
+        .with_membership(&[PLAYER_TOUCH_GROUP])
+        .with_whitelist(&[PLAYER_TOUCH_GROUP])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collision_groups() {
+        let player = get_collision_groups(EntityType::Player);
+
+        assert!(!player.can_interact_with_groups(&player));
+        assert!(player.can_interact_with_groups(&get_collision_groups(EntityType::Bomb)));
+        assert!(player.can_interact_with_groups(&get_collision_groups(EntityType::HardBlock)));
+        assert!(!get_collision_groups(EntityType::Bomb)
+            .can_interact_with_groups(&get_collision_groups(EntityType::SoftBlock)));
+        assert!(get_player_touch_collision_groups()
+            .can_interact_with_groups(&get_player_touch_collision_groups()));
+        assert!(!player.can_interact_with_groups(&get_player_touch_collision_groups()));
+    }
+}
@@ -1,10 +1,12 @@
+use crate::game_states::play_state::bombs::BombType;
+use crate::game_states::play_state::diseases::Disease;
 use crate::game_states::play_state::players::{Direction, PlayerCommand, PlayerId};
+use crate::game_states::play_state::power_ups::PowerUpType;
 use crate::tiles::animation::Animation;
-use crate::tiles::tileset::TileId;
+use crate::tiles::tileset::{TileId, TilePosition};
 use legion::entity::Entity;
 use nphysics2d::object::{DefaultBodyHandle, DefaultColliderHandle};
-use piston::input::Button;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -13,6 +15,9 @@ pub struct ScreenPosition(pub [f64; 2]);
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Layer(pub usize);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridPosition(pub TilePosition);
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct CurrentTileId(pub TileId);
 
@@ -22,9 +27,6 @@ pub struct DefaultTileId(pub TileId);
 #[derive(Clone, Debug, PartialEq)]
 pub struct MoveDirectionStack(pub Vec<Direction>);
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Controls(pub HashMap<Button, PlayerCommand>);
-
 #[derive(Clone, Debug)]
 pub enum AnimationType {
     Shared(Arc<RwLock<Animation>>),
@@ -34,33 +36,137 @@ pub enum AnimationType {
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Player(pub PlayerId);
 
+/// In tiles per second
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct MovementSpeed(pub f64);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BombCapacity(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CanKick;
+
+/// The type of bomb the player places
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BombVariant(pub BombType);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CanLineBomb;
+
+/// Time left to press the bomb button a second time to drop a line bomb
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineBombWindow(pub f64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CanThrow;
+
+/// A thrown bomb, which still has to fly `distance` pixels
+/// and has bounced `bounces` times over occupied tiles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Airborne {
+    pub direction: Direction,
+    pub distance: f64,
+    pub bounces: u32,
+}
+
+/// `time_left` is in seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diseased {
+    pub disease: Disease,
+    pub time_left: f64,
+}
+
+/// Bombs of the player don't have a fuse and are set off with `PlayerCommand::Detonate`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RemoteControl;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sliding(pub Direction);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lives(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpawnPosition(pub TilePosition);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dying(pub f64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Invulnerability(pub f64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hidden;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerUp(pub PowerUpType);
+
 #[derive(Clone)]
 pub struct Tilemap(pub Arc<crate::tiles::tilemap::Tilemap>);
 
+#[derive(Clone)]
+pub struct Map(pub Arc<RwLock<crate::game_states::play_state::map::Map>>);
+
 #[derive(Clone)]
 pub struct Tileset(pub Arc<crate::tiles::tileset::Tileset>);
 
 #[derive(Clone)]
 pub struct AssetStorage(pub Arc<RwLock<crate::utils::asset_storage::AssetStorage>>);
 
+/// Every random roll of a round goes through this, so a replay can roll the same values
+pub struct SeededRng(pub rand::rngs::StdRng);
+
 #[derive(Clone, Copy, Debug)]
 pub struct SpawnBomb(pub Entity);
 
+#[derive(Clone, Copy, Debug)]
+pub struct RemoteDetonation(pub Entity);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fuse(pub f64);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Detonation(pub Entity);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlastRange(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BombOwner(pub Entity);
+
+/// Counts up with every bomb a player places, so the oldest one can be told apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PlacementOrder(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lifetime(pub f64);
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BodyHandle(pub DefaultBodyHandle);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ColliderHandle(pub DefaultColliderHandle);
 
+/// Multiplies the colors of the sprite
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tint(pub [f32; 3]);
+
+/// Rotates the sprite clockwise around its center, in degrees
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rotation(pub f64);
+
+/// The sensor with which players notice each other when they don't collide
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TouchSensorHandle(pub DefaultColliderHandle);
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum EntityType {
     Player,
     Bomb,
     SoftBlock,
     HardBlock,
+    Flame,
+    PowerUp,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -69,6 +175,10 @@ pub struct Collision(pub bool);
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PlayerEntity(pub Entity);
 
+/// The second player of a collision between two players
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OtherPlayerEntity(pub Entity);
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BombEntity(pub Entity);
 
@@ -78,5 +188,8 @@ pub struct SoftBlockEntity(pub Entity);
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct HardBlockEntity(pub Entity);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerUpEntity(pub Entity);
+
 #[derive(Clone, Debug)]
 pub struct DeactivatedCommands(pub HashSet<PlayerCommand>);
@@ -0,0 +1,342 @@
+use crate::game_states::play_state::players::{Direction, PlayerCommand, PlayerId};
+use piston::input::{Button, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const CONTROLS_PATH: &str = "assets/controls.toml";
+
+/// The commands in the order in which they are listed in the controls file and screen
+pub const COMMANDS: [PlayerCommand; 6] = [
+    PlayerCommand::Movement(Direction::Left),
+    PlayerCommand::Movement(Direction::Right),
+    PlayerCommand::Movement(Direction::Up),
+    PlayerCommand::Movement(Direction::Down),
+    PlayerCommand::Bomb,
+    PlayerCommand::Detonate,
+];
+
+pub fn get_command_name(command: PlayerCommand) -> &'static str {
+    match command {
+        PlayerCommand::Movement(Direction::Left) => "left",
+        PlayerCommand::Movement(Direction::Right) => "right",
+        PlayerCommand::Movement(Direction::Up) => "up",
+        PlayerCommand::Movement(Direction::Down) => "down",
+        PlayerCommand::Bomb => "bomb",
+        PlayerCommand::Detonate => "detonate",
+    }
+}
+
+pub fn get_button_name(button: Button) -> String {
+    match button {
+        Button::Keyboard(key) => format!("{:?}", key),
+        _ => format!("{:?}", button),
+    }
+}
+
+/// The buttons of one player
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ControlsProfile {
+    #[serde(with = "button_format")]
+    pub left: Button,
+    #[serde(with = "button_format")]
+    pub right: Button,
+    #[serde(with = "button_format")]
+    pub up: Button,
+    #[serde(with = "button_format")]
+    pub down: Button,
+    #[serde(with = "button_format")]
+    pub bomb: Button,
+    #[serde(with = "button_format")]
+    pub detonate: Button,
+}
+
+impl ControlsProfile {
+    /// The default layouts share one keyboard, so no key is bound twice
+    pub fn default_for(player_id: PlayerId) -> ControlsProfile {
+        let ([left, right, up, down], bomb, detonate) = match player_id {
+            PlayerId::Player1 => (
+                [Key::Left, Key::Right, Key::Up, Key::Down],
+                Key::RCtrl,
+                Key::RShift,
+            ),
+            PlayerId::Player2 => ([Key::A, Key::D, Key::W, Key::S], Key::LCtrl, Key::LShift),
+            PlayerId::Player3 => ([Key::J, Key::L, Key::I, Key::K], Key::O, Key::P),
+            PlayerId::Player4 => (
+                [Key::NumPad4, Key::NumPad6, Key::NumPad8, Key::NumPad5],
+                Key::NumPad0,
+                Key::NumPadPeriod,
+            ),
+        };
+
+        ControlsProfile {
+            left: Button::Keyboard(left),
+            right: Button::Keyboard(right),
+            up: Button::Keyboard(up),
+            down: Button::Keyboard(down),
+            bomb: Button::Keyboard(bomb),
+            detonate: Button::Keyboard(detonate),
+        }
+    }
+
+    pub fn get_button(&self, command: PlayerCommand) -> Button {
+        match command {
+            PlayerCommand::Movement(Direction::Left) => self.left,
+            PlayerCommand::Movement(Direction::Right) => self.right,
+            PlayerCommand::Movement(Direction::Up) => self.up,
+            PlayerCommand::Movement(Direction::Down) => self.down,
+            PlayerCommand::Bomb => self.bomb,
+            PlayerCommand::Detonate => self.detonate,
+        }
+    }
+
+    fn set_button(&mut self, command: PlayerCommand, button: Button) {
+        let binding = match command {
+            PlayerCommand::Movement(Direction::Left) => &mut self.left,
+            PlayerCommand::Movement(Direction::Right) => &mut self.right,
+            PlayerCommand::Movement(Direction::Up) => &mut self.up,
+            PlayerCommand::Movement(Direction::Down) => &mut self.down,
+            PlayerCommand::Bomb => &mut self.bomb,
+            PlayerCommand::Detonate => &mut self.detonate,
+        };
+        *binding = button;
+    }
+}
+
+/// The controls of all players, stored in a TOML file with one table per player.
+/// Players missing from the file keep their default layout.
+/// Only the players of a match need distinct buttons, so the profiles of players
+/// who don't take part may share buttons with the others.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlsConfig {
+    player1: ControlsProfile,
+    player2: ControlsProfile,
+    player3: ControlsProfile,
+    player4: ControlsProfile,
+}
+
+impl Default for ControlsConfig {
+    fn default() -> Self {
+        ControlsConfig {
+            player1: ControlsProfile::default_for(PlayerId::Player1),
+            player2: ControlsProfile::default_for(PlayerId::Player2),
+            player3: ControlsProfile::default_for(PlayerId::Player3),
+            player4: ControlsProfile::default_for(PlayerId::Player4),
+        }
+    }
+}
+
+impl ControlsConfig {
+    /// Falls back to the default layouts when there is no controls file yet
+    pub fn load(path: &Path, player_ids: &[PlayerId]) -> Result<ControlsConfig, String> {
+        if !path.exists() {
+            return Ok(ControlsConfig::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read {}: {}", path.display(), error))?;
+        Self::from_toml(&content, player_ids)
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Rejects the controls if two of the players share a button
+    pub fn from_toml(content: &str, player_ids: &[PlayerId]) -> Result<ControlsConfig, String> {
+        let controls_config: ControlsConfig =
+            toml::from_str(content).map_err(|error| error.to_string())?;
+        controls_config.validate(player_ids)?;
+
+        Ok(controls_config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = toml::to_string(self).map_err(|error| error.to_string())?;
+        std::fs::write(path, content)
+            .map_err(|error| format!("Cannot write {}: {}", path.display(), error))
+    }
+
+    pub fn get_profile(&self, player_id: PlayerId) -> &ControlsProfile {
+        match player_id {
+            PlayerId::Player1 => &self.player1,
+            PlayerId::Player2 => &self.player2,
+            PlayerId::Player3 => &self.player3,
+            PlayerId::Player4 => &self.player4,
+        }
+    }
+
+    fn get_profile_mut(&mut self, player_id: PlayerId) -> &mut ControlsProfile {
+        match player_id {
+            PlayerId::Player1 => &mut self.player1,
+            PlayerId::Player2 => &mut self.player2,
+            PlayerId::Player3 => &mut self.player3,
+            PlayerId::Player4 => &mut self.player4,
+        }
+    }
+
+    /// Rejects the button if it already triggers another command of one of the players
+    pub fn bind(
+        &mut self,
+        player_ids: &[PlayerId],
+        player_id: PlayerId,
+        command: PlayerCommand,
+        button: Button,
+    ) -> Result<(), String> {
+        match self.get_binding(player_ids, button) {
+            Some(binding) if binding != (player_id, command) => {
+                return Err(Self::describe_conflict(
+                    button,
+                    (player_id, command),
+                    binding,
+                ));
+            }
+            _ => {}
+        }
+
+        self.get_profile_mut(player_id).set_button(command, button);
+        Ok(())
+    }
+
+    fn get_bindings<'a>(
+        &'a self,
+        player_ids: &'a [PlayerId],
+    ) -> impl Iterator<Item = (Button, (PlayerId, PlayerCommand))> + 'a {
+        player_ids.iter().flat_map(move |player_id| {
+            COMMANDS.iter().map(move |command| {
+                (
+                    self.get_profile(*player_id).get_button(*command),
+                    (*player_id, *command),
+                )
+            })
+        })
+    }
+
+    fn get_binding(
+        &self,
+        player_ids: &[PlayerId],
+        button: Button,
+    ) -> Option<(PlayerId, PlayerCommand)> {
+        self.get_bindings(player_ids)
+            .find(|(other_button, _)| *other_button == button)
+            .map(|(_, binding)| binding)
+    }
+
+    fn validate(&self, player_ids: &[PlayerId]) -> Result<(), String> {
+        let mut bindings = HashMap::new();
+
+        for (button, binding) in self.get_bindings(player_ids) {
+            if let Some(other_binding) = bindings.insert(button, binding) {
+                return Err(Self::describe_conflict(button, binding, other_binding));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn describe_conflict(
+        button: Button,
+        (player_id, command): (PlayerId, PlayerCommand),
+        (other_player_id, other_command): (PlayerId, PlayerCommand),
+    ) -> String {
+        format!(
+            "{} is bound to {} of player {} and to {} of player {}",
+            get_button_name(button),
+            get_command_name(other_command),
+            u32::from(other_player_id),
+            get_command_name(command),
+            u32::from(player_id),
+        )
+    }
+}
+
+/// Buttons are written by their key name, e.g. `bomb = "RCtrl"`
+mod button_format {
+    use piston::input::{Button, Key};
+    use serde::ser::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(button: &Button, serializer: S) -> Result<S::Ok, S::Error> {
+        match button {
+            Button::Keyboard(key) => key.serialize(serializer),
+            _ => Err(S::Error::custom(format!("{:?} cannot be bound", button))),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Button, D::Error> {
+        Key::deserialize(deserializer).map(Button::Keyboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_controls_do_not_overlap() {
+        assert_eq!(ControlsConfig::default().validate(&PlayerId::ALL), Ok(()));
+    }
+
+    #[test]
+    fn test_controls_config_from_toml() {
+        let player_ids = [PlayerId::Player1, PlayerId::Player2];
+        let controls_config = ControlsConfig::from_toml(
+            r#"
+            [player2]
+            left = "Left"
+            right = "Right"
+            up = "Up"
+            down = "Down"
+            bomb = "Space"
+            detonate = "Tab"
+            "#,
+            &player_ids,
+        );
+        assert_eq!(
+            controls_config,
+            Err("Left is bound to left of player 1 and to left of player 2".to_string())
+        );
+
+        // player 4 doesn't take part, so their buttons may be taken by player 1
+        let mut controls_config = ControlsConfig::from_toml(
+            r#"
+            [player1]
+            left = "Z"
+            right = "X"
+            up = "C"
+            down = "V"
+            bomb = "Space"
+            detonate = "NumPad0"
+            "#,
+            &player_ids,
+        )
+        .unwrap();
+        assert_eq!(
+            controls_config.get_profile(PlayerId::Player1).bomb,
+            Button::Keyboard(Key::Space)
+        );
+        assert_eq!(
+            controls_config.get_profile(PlayerId::Player2),
+            &ControlsProfile::default_for(PlayerId::Player2)
+        );
+        assert!(controls_config.validate(&PlayerId::ALL).is_err());
+
+        let mut bind = |button| {
+            controls_config.bind(
+                &player_ids,
+                PlayerId::Player1,
+                PlayerCommand::Bomb,
+                Button::Keyboard(button),
+            )
+        };
+        assert!(bind(Key::W).is_err());
+        assert!(bind(Key::NumPad0).is_err());
+        assert!(bind(Key::Space).is_ok());
+        assert!(bind(Key::I).is_ok());
+        assert!(bind(Key::B).is_ok());
+
+        let saved = toml::to_string(&controls_config).unwrap();
+        assert_eq!(
+            ControlsConfig::from_toml(&saved, &player_ids),
+            Ok(controls_config)
+        );
+    }
+}
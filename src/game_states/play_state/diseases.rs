@@ -0,0 +1,131 @@
+use crate::game_states::play_state::players::Direction;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// In seconds
+pub const DISEASE_DURATION: f64 = 10.0;
+/// In tiles per second
+pub const SLOW_MOVEMENT_SPEED: f64 = 1.0;
+pub const TINY_BLAST_RANGE: u32 = 1;
+
+/// Debuffs given by the skull power up, which spread to other players on contact
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Disease {
+    ReversedControls,
+    Slowness,
+    NonstopBombs,
+    NoBombs,
+    TinyBlastRange,
+}
+
+impl Disease {
+    pub const ALL: [Disease; 5] = [
+        Disease::ReversedControls,
+        Disease::Slowness,
+        Disease::NonstopBombs,
+        Disease::NoBombs,
+        Disease::TinyBlastRange,
+    ];
+
+    pub fn random(rng: &mut impl Rng) -> Disease {
+        *Disease::ALL.choose(rng).unwrap()
+    }
+}
+
+/// The direction a movement command moves a player with the disease
+pub fn get_controlled_direction(direction: Direction, disease: Option<Disease>) -> Direction {
+    if disease == Some(Disease::ReversedControls) {
+        direction.get_opposite()
+    } else {
+        direction
+    }
+}
+
+/// Whether the movement directions turn around when the disease of a player changes.
+/// Held buttons would then be released as the other direction, so the player has to stop.
+pub fn flips_controls(disease: Option<Disease>, next_disease: Option<Disease>) -> bool {
+    (disease == Some(Disease::ReversedControls))
+        != (next_disease == Some(Disease::ReversedControls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_states::play_state::input::CommandState;
+    use crate::game_states::play_state::players;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    struct TestPlayer {
+        disease: Option<Disease>,
+        move_direction_stack: Vec<Direction>,
+    }
+
+    impl TestPlayer {
+        fn set_disease(&mut self, disease: Option<Disease>) {
+            if flips_controls(self.disease, disease) {
+                self.move_direction_stack.clear();
+            }
+            self.disease = disease;
+        }
+
+        fn handle_command(&mut self, direction: Direction, state: CommandState) {
+            players::update_move_direction_stack(
+                &mut self.move_direction_stack,
+                get_controlled_direction(direction, self.disease),
+                state,
+            );
+        }
+    }
+
+    #[test]
+    fn test_random_disease() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let diseases = (0..100)
+            .map(|_| Disease::random(&mut rng))
+            .collect::<Vec<_>>();
+
+        assert!(Disease::ALL
+            .iter()
+            .all(|disease| diseases.contains(disease)));
+    }
+
+    #[test]
+    fn test_reversed_controls() {
+        let mut player = TestPlayer {
+            disease: None,
+            move_direction_stack: vec![],
+        };
+
+        player.handle_command(Direction::Left, CommandState::Pressed);
+        player.set_disease(Some(Disease::ReversedControls));
+        assert_eq!(player.move_direction_stack, vec![]);
+
+        player.handle_command(Direction::Left, CommandState::Released);
+        player.handle_command(Direction::Up, CommandState::Pressed);
+        assert_eq!(player.move_direction_stack, vec![Direction::Down]);
+
+        // infected twice, the held direction stays reversed
+        player.set_disease(Some(Disease::ReversedControls));
+        assert_eq!(player.move_direction_stack, vec![Direction::Down]);
+
+        player.set_disease(None);
+        assert_eq!(player.move_direction_stack, vec![]);
+
+        // the button held while reversed doesn't release anything once cured
+        player.handle_command(Direction::Right, CommandState::Pressed);
+        player.handle_command(Direction::Up, CommandState::Released);
+        assert_eq!(player.move_direction_stack, vec![Direction::Right]);
+
+        player.set_disease(Some(Disease::Slowness));
+        assert_eq!(player.move_direction_stack, vec![Direction::Right]);
+
+        player.set_disease(Some(Disease::ReversedControls));
+        player.handle_command(Direction::Right, CommandState::Released);
+        player.handle_command(Direction::Down, CommandState::Pressed);
+        assert_eq!(player.move_direction_stack, vec![Direction::Up]);
+
+        player.set_disease(Some(Disease::NoBombs));
+        assert_eq!(player.move_direction_stack, vec![]);
+    }
+}
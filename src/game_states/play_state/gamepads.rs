@@ -0,0 +1,428 @@
+use crate::game_states::play_state::input::{CommandEvent, CommandState};
+use crate::game_states::play_state::players::{Direction, PlayerCommand, PlayerId};
+use piston::input::event_id::EventId;
+use piston::input::{Event, Key};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Stick positions closer to the center than this are ignored
+pub const STICK_DEADZONE: f64 = 0.3;
+
+pub type GamepadId = usize;
+
+const GAMEPAD_EVENT_ID: EventId = EventId("bomberman/gamepad");
+
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    South,
+    East,
+}
+
+impl GamepadButton {
+    fn get_command(self) -> PlayerCommand {
+        match self {
+            GamepadButton::DPadUp => PlayerCommand::Movement(Direction::Up),
+            GamepadButton::DPadDown => PlayerCommand::Movement(Direction::Down),
+            GamepadButton::DPadLeft => PlayerCommand::Movement(Direction::Left),
+            GamepadButton::DPadRight => PlayerCommand::Movement(Direction::Right),
+            GamepadButton::South => PlayerCommand::Bomb,
+            GamepadButton::East => PlayerCommand::Detonate,
+        }
+    }
+}
+
+/// Positive values point right and up
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+}
+
+impl GamepadAxis {
+    fn get_direction(self, position: f64) -> Option<Direction> {
+        if position.abs() < STICK_DEADZONE {
+            return None;
+        }
+
+        match (self, position > 0.0) {
+            (GamepadAxis::LeftStickX, true) => Some(Direction::Right),
+            (GamepadAxis::LeftStickX, false) => Some(Direction::Left),
+            (GamepadAxis::LeftStickY, true) => Some(Direction::Up),
+            (GamepadAxis::LeftStickY, false) => Some(Direction::Down),
+        }
+    }
+}
+
+/// Raw input of a gamepad, independent of the library which reads the devices.
+/// Without the `gamepad` feature, only the tests create them.
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    ButtonPressed(GamepadId, GamepadButton),
+    ButtonReleased(GamepadId, GamepadButton),
+    AxisChanged(GamepadId, GamepadAxis, f64),
+}
+
+pub trait GamepadBackend {
+    /// Returns the events since the last poll without blocking
+    fn poll(&mut self) -> Vec<GamepadEvent>;
+}
+
+/// Used when the game is built without gamepad support or no gamepad library could be initialized
+pub struct NoGamepads;
+
+impl GamepadBackend for NoGamepads {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        vec![]
+    }
+}
+
+/// Fails if the gamepad library cannot be initialized
+pub fn create_backend() -> Result<Box<dyn GamepadBackend>, String> {
+    #[cfg(feature = "gamepad")]
+    {
+        gilrs_backend::GilrsBackend::new()
+            .map(|backend| Box::new(backend) as Box<dyn GamepadBackend>)
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    {
+        Ok(Box::new(NoGamepads))
+    }
+}
+
+/// Wraps the command events of one poll, so the state manager can pass them
+/// down the state stack like any other event
+pub fn create_event(commands: Vec<CommandEvent>) -> Event {
+    Event::Custom(GAMEPAD_EVENT_ID, Arc::new(commands), None)
+}
+
+pub fn get_commands(event: &Event) -> Option<&Vec<CommandEvent>> {
+    match event {
+        Event::Custom(id, commands, _) if *id == GAMEPAD_EVENT_ID => commands.downcast_ref(),
+        _ => None,
+    }
+}
+
+/// The key a pressed command stands for on the screens between rounds,
+/// so those can be used with a gamepad too
+pub fn get_menu_key(command_event: &CommandEvent) -> Option<Key> {
+    if command_event.state != CommandState::Pressed {
+        return None;
+    }
+
+    let key = match command_event.command {
+        PlayerCommand::Movement(Direction::Left) => Key::Left,
+        PlayerCommand::Movement(Direction::Right) => Key::Right,
+        PlayerCommand::Movement(Direction::Up) => Key::Up,
+        PlayerCommand::Movement(Direction::Down) => Key::Down,
+        PlayerCommand::Bomb => Key::Return,
+        PlayerCommand::Detonate => Key::Escape,
+    };
+
+    Some(key)
+}
+
+/// Assigns gamepads to players as they connect and translates their events into player commands
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gamepads {
+    player_ids: Vec<PlayerId>,
+    assignments: BTreeMap<GamepadId, PlayerId>,
+    stick_directions: HashMap<(GamepadId, GamepadAxis), Direction>,
+    held_commands: HashMap<GamepadId, Vec<PlayerCommand>>,
+}
+
+impl Gamepads {
+    pub fn new(player_ids: &[PlayerId]) -> Gamepads {
+        Gamepads {
+            player_ids: player_ids.to_vec(),
+            assignments: BTreeMap::new(),
+            stick_directions: HashMap::new(),
+            held_commands: HashMap::new(),
+        }
+    }
+
+    pub fn get_player_id(&self, gamepad: GamepadId) -> Option<PlayerId> {
+        self.assignments.get(&gamepad).copied()
+    }
+
+    pub fn handle_events(&mut self, events: Vec<GamepadEvent>) -> Vec<CommandEvent> {
+        events
+            .into_iter()
+            .flat_map(|event| self.handle_event(event))
+            .collect()
+    }
+
+    pub fn handle_event(&mut self, event: GamepadEvent) -> Vec<CommandEvent> {
+        match event {
+            GamepadEvent::Connected(gamepad) => {
+                self.connect(gamepad);
+                vec![]
+            }
+            GamepadEvent::Disconnected(gamepad) => self.disconnect(gamepad),
+            GamepadEvent::ButtonPressed(gamepad, button) => {
+                self.press(gamepad, button.get_command())
+            }
+            GamepadEvent::ButtonReleased(gamepad, button) => {
+                self.release(gamepad, button.get_command())
+            }
+            GamepadEvent::AxisChanged(gamepad, axis, position) => {
+                let direction = axis.get_direction(position);
+                let old_direction = match direction {
+                    Some(direction) => self.stick_directions.insert((gamepad, axis), direction),
+                    None => self.stick_directions.remove(&(gamepad, axis)),
+                };
+
+                if direction == old_direction {
+                    return vec![];
+                }
+
+                let mut commands = vec![];
+                if let Some(old_direction) = old_direction {
+                    commands.extend(self.release(gamepad, PlayerCommand::Movement(old_direction)));
+                }
+                if let Some(direction) = direction {
+                    commands.extend(self.press(gamepad, PlayerCommand::Movement(direction)));
+                }
+                commands
+            }
+        }
+    }
+
+    /// The gamepad goes to the first player without one. Gamepads beyond the player count stay unused.
+    fn connect(&mut self, gamepad: GamepadId) -> Option<PlayerId> {
+        if let Some(player_id) = self.get_player_id(gamepad) {
+            return Some(player_id);
+        }
+
+        let player_id = *self
+            .player_ids
+            .iter()
+            .find(|player_id| !self.assignments.values().any(|other| other == *player_id))?;
+        self.assignments.insert(gamepad, player_id);
+
+        Some(player_id)
+    }
+
+    /// Releases everything the gamepad was holding, so the player doesn't keep walking
+    fn disconnect(&mut self, gamepad: GamepadId) -> Vec<CommandEvent> {
+        let commands = match self.get_player_id(gamepad) {
+            Some(player_id) => self
+                .held_commands
+                .remove(&gamepad)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|command| CommandEvent::new(player_id, command, CommandState::Released))
+                .collect(),
+            None => vec![],
+        };
+
+        self.assignments.remove(&gamepad);
+        self.stick_directions
+            .retain(|(other_gamepad, _), _| *other_gamepad != gamepad);
+
+        commands
+    }
+
+    fn press(&mut self, gamepad: GamepadId, command: PlayerCommand) -> Vec<CommandEvent> {
+        // some backends don't report the gamepads which were connected before the game started
+        match self.connect(gamepad) {
+            Some(player_id) => {
+                self.held_commands.entry(gamepad).or_default().push(command);
+                vec![CommandEvent::new(player_id, command, CommandState::Pressed)]
+            }
+            None => vec![],
+        }
+    }
+
+    fn release(&mut self, gamepad: GamepadId, command: PlayerCommand) -> Vec<CommandEvent> {
+        let held_commands = self.held_commands.entry(gamepad).or_default();
+        match held_commands.iter().position(|held| *held == command) {
+            Some(index) => {
+                held_commands.remove(index);
+                let player_id = self.get_player_id(gamepad).unwrap();
+                vec![CommandEvent::new(
+                    player_id,
+                    command,
+                    CommandState::Released,
+                )]
+            }
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(feature = "gamepad")]
+mod gilrs_backend {
+    use super::{GamepadAxis, GamepadBackend, GamepadButton, GamepadEvent};
+    use gilrs::{Axis, Button, EventType, Gilrs};
+
+    pub struct GilrsBackend {
+        gilrs: Gilrs,
+        is_first_poll: bool,
+    }
+
+    impl GilrsBackend {
+        pub fn new() -> Result<GilrsBackend, String> {
+            Ok(GilrsBackend {
+                gilrs: Gilrs::new().map_err(|error| error.to_string())?,
+                is_first_poll: true,
+            })
+        }
+    }
+
+    impl GamepadBackend for GilrsBackend {
+        fn poll(&mut self) -> Vec<GamepadEvent> {
+            let mut events = vec![];
+
+            // gilrs only reports gamepads that connect after it has been created
+            if self.is_first_poll {
+                self.is_first_poll = false;
+                events.extend(
+                    self.gilrs
+                        .gamepads()
+                        .map(|(id, _)| GamepadEvent::Connected(id.into())),
+                );
+            }
+
+            while let Some(event) = self.gilrs.next_event() {
+                let gamepad = event.id.into();
+                let button = |button| match button {
+                    Button::DPadUp => Some(GamepadButton::DPadUp),
+                    Button::DPadDown => Some(GamepadButton::DPadDown),
+                    Button::DPadLeft => Some(GamepadButton::DPadLeft),
+                    Button::DPadRight => Some(GamepadButton::DPadRight),
+                    Button::South => Some(GamepadButton::South),
+                    Button::East => Some(GamepadButton::East),
+                    _ => None,
+                };
+
+                let event = match event.event {
+                    EventType::Connected => Some(GamepadEvent::Connected(gamepad)),
+                    EventType::Disconnected => Some(GamepadEvent::Disconnected(gamepad)),
+                    EventType::ButtonPressed(gilrs_button, _) => button(gilrs_button)
+                        .map(|button| GamepadEvent::ButtonPressed(gamepad, button)),
+                    EventType::ButtonReleased(gilrs_button, _) => button(gilrs_button)
+                        .map(|button| GamepadEvent::ButtonReleased(gamepad, button)),
+                    EventType::AxisChanged(Axis::LeftStickX, position, _) => {
+                        Some(GamepadEvent::AxisChanged(
+                            gamepad,
+                            GamepadAxis::LeftStickX,
+                            position.into(),
+                        ))
+                    }
+                    EventType::AxisChanged(Axis::LeftStickY, position, _) => {
+                        Some(GamepadEvent::AxisChanged(
+                            gamepad,
+                            GamepadAxis::LeftStickY,
+                            position.into(),
+                        ))
+                    }
+                    _ => None,
+                };
+
+                events.extend(event);
+            }
+
+            events
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gamepad_assignment() {
+        let mut gamepads = Gamepads::new(&[PlayerId::Player1, PlayerId::Player3]);
+
+        gamepads.handle_events(vec![
+            GamepadEvent::Connected(4),
+            GamepadEvent::Connected(7),
+            GamepadEvent::Connected(9),
+        ]);
+        assert_eq!(gamepads.get_player_id(4), Some(PlayerId::Player1));
+        assert_eq!(gamepads.get_player_id(7), Some(PlayerId::Player3));
+        assert_eq!(gamepads.get_player_id(9), None);
+        assert_eq!(
+            gamepads.handle_event(GamepadEvent::ButtonPressed(9, GamepadButton::South)),
+            vec![]
+        );
+
+        // the player of an unplugged gamepad stops moving and the next gamepad takes over
+        assert_eq!(
+            gamepads.handle_events(vec![
+                GamepadEvent::ButtonPressed(4, GamepadButton::DPadLeft),
+                GamepadEvent::Disconnected(4),
+                GamepadEvent::ButtonPressed(9, GamepadButton::South),
+            ]),
+            vec![
+                CommandEvent::new(
+                    PlayerId::Player1,
+                    PlayerCommand::Movement(Direction::Left),
+                    CommandState::Pressed
+                ),
+                CommandEvent::new(
+                    PlayerId::Player1,
+                    PlayerCommand::Movement(Direction::Left),
+                    CommandState::Released
+                ),
+                CommandEvent::new(
+                    PlayerId::Player1,
+                    PlayerCommand::Bomb,
+                    CommandState::Pressed
+                ),
+            ]
+        );
+        assert_eq!(gamepads.get_player_id(4), None);
+    }
+
+    #[test]
+    fn test_gamepad_event() {
+        let commands = vec![CommandEvent::new(
+            PlayerId::Player1,
+            PlayerCommand::Bomb,
+            CommandState::Pressed,
+        )];
+        let event = create_event(commands.clone());
+
+        assert_eq!(get_commands(&event), Some(&commands));
+        assert_eq!(get_menu_key(&commands[0]), Some(Key::Return));
+        assert_eq!(
+            get_commands(&Event::Custom(EventId("other"), Arc::new(commands), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stick_deadzone() {
+        let mut gamepads = Gamepads::new(&[PlayerId::Player2]);
+        let up = PlayerCommand::Movement(Direction::Up);
+        let down = PlayerCommand::Movement(Direction::Down);
+
+        assert_eq!(
+            gamepads.handle_events(vec![
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickY, STICK_DEADZONE / 2.0),
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickY, 0.9),
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickY, 1.0),
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickY, -0.9),
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickY, 0.0),
+            ]),
+            vec![
+                CommandEvent::new(PlayerId::Player2, up, CommandState::Pressed),
+                CommandEvent::new(PlayerId::Player2, up, CommandState::Released),
+                CommandEvent::new(PlayerId::Player2, down, CommandState::Pressed),
+                CommandEvent::new(PlayerId::Player2, down, CommandState::Released),
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use crate::game_states::play_state::components::EntityType;
+use crate::tiles::tilemap::Tilemap;
+use crate::tiles::tileset::TilePosition;
+use legion::entity::Entity;
+use std::collections::HashMap;
+
+/// Records which entities occupy which tile of the arena.
+/// Systems keep it up to date whenever an entity spawns, moves or dies.
+pub struct ArenaGrid {
+    columns: u32,
+    rows: u32,
+    tile_width: u32,
+    tile_height: u32,
+    cells: Vec<Vec<(EntityType, Entity)>>,
+    positions: HashMap<Entity, TilePosition>,
+}
+
+impl ArenaGrid {
+    pub fn new(tilemap: &Tilemap) -> ArenaGrid {
+        ArenaGrid {
+            columns: tilemap.width,
+            rows: tilemap.height,
+            tile_width: tilemap.tile_width,
+            tile_height: tilemap.tile_height,
+            cells: vec![vec![]; (tilemap.width * tilemap.height) as usize],
+            positions: HashMap::new(),
+        }
+    }
+
+    fn get_cell_index(&self, [x, y]: TilePosition) -> Option<usize> {
+        let column = x / self.tile_width;
+        let row = y / self.tile_height;
+
+        if column < self.columns && row < self.rows {
+            Some((row * self.columns + column) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, entity_type: EntityType, position: TilePosition) {
+        self.remove(entity);
+
+        if let Some(index) = self.get_cell_index(position) {
+            self.cells[index].push((entity_type, entity));
+            self.positions.insert(entity, position);
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(position) = self.positions.remove(&entity) {
+            let index = self.get_cell_index(position).unwrap();
+            self.cells[index].retain(|(_, other_entity)| *other_entity != entity);
+        }
+    }
+
+    /// Moves an entity, which is already part of the grid, to another tile
+    pub fn move_entity(&mut self, entity: Entity, position: TilePosition) {
+        let entity_type = match self.get_position(entity) {
+            Some(old_position) if old_position == position => return,
+            Some(old_position) => {
+                let index = self.get_cell_index(old_position).unwrap();
+                self.cells[index]
+                    .iter()
+                    .find(|(_, other_entity)| *other_entity == entity)
+                    .unwrap()
+                    .0
+            }
+            None => return,
+        };
+
+        self.insert(entity, entity_type, position);
+    }
+
+    pub fn get_position(&self, entity: Entity) -> Option<TilePosition> {
+        self.positions.get(&entity).copied()
+    }
+
+    pub fn get_entities(
+        &self,
+        position: TilePosition,
+        entity_type: EntityType,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        self.get_cell_index(position)
+            .map(|index| self.cells[index].as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .filter(move |(other_entity_type, _)| *other_entity_type == entity_type)
+            .map(|(_, entity)| *entity)
+    }
+
+    pub fn contains(&self, position: TilePosition, entity_type: EntityType) -> bool {
+        self.get_entities(position, entity_type).next().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use legion::world::Universe;
+
+    #[test]
+    fn test_arena_grid() {
+        let tilemap = Tilemap::with_size(3, 3);
+
+        let mut world = Universe::new().create_world();
+        let entities = world.insert((), vec![(0,), (1,)]).to_vec();
+        let (player, bomb) = (entities[0], entities[1]);
+
+        let mut grid = ArenaGrid::new(&tilemap);
+        grid.insert(player, EntityType::Player, [32, 32]);
+        grid.insert(bomb, EntityType::Bomb, [32, 32]);
+        assert!(grid.contains([32, 32], EntityType::Player));
+        assert_eq!(
+            grid.get_entities([32, 32], EntityType::Bomb)
+                .collect::<Vec<_>>(),
+            vec![bomb]
+        );
+
+        grid.move_entity(player, [64, 32]);
+        assert!(!grid.contains([32, 32], EntityType::Player));
+        assert!(grid.contains([64, 32], EntityType::Player));
+        assert_eq!(grid.get_position(player), Some([64, 32]));
+
+        grid.remove(bomb);
+        assert!(!grid.contains([32, 32], EntityType::Bomb));
+        assert_eq!(grid.get_position(bomb), None);
+
+        // outside of the arena
+        grid.insert(bomb, EntityType::Bomb, [96, 0]);
+        assert_eq!(grid.get_position(bomb), None);
+    }
+}
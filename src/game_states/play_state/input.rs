@@ -0,0 +1,212 @@
+use crate::game_states::play_state::controls::{ControlsConfig, COMMANDS};
+use crate::game_states::play_state::gamepads;
+use crate::game_states::play_state::players::{PlayerCommand, PlayerId};
+use piston::input::{Button, ButtonEvent, ButtonState, Event, UpdateEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommandState {
+    Pressed,
+    Released,
+}
+
+impl From<ButtonState> for CommandState {
+    fn from(button_state: ButtonState) -> Self {
+        match button_state {
+            ButtonState::Press => CommandState::Pressed,
+            ButtonState::Release => CommandState::Released,
+        }
+    }
+}
+
+/// A player started or stopped a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CommandEvent {
+    pub player_id: PlayerId,
+    #[serde(with = "command_format")]
+    pub command: PlayerCommand,
+    pub state: CommandState,
+}
+
+impl CommandEvent {
+    pub fn new(player_id: PlayerId, command: PlayerCommand, state: CommandState) -> CommandEvent {
+        CommandEvent {
+            player_id,
+            command,
+            state,
+        }
+    }
+}
+
+/// The command events of all players for the current tick, in the order they happened.
+/// It is empty on every event that isn't an update.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerCommands(pub Vec<CommandEvent>);
+
+/// Anything that drives players, e.g. a keyboard, a gamepad, an AI or a replay
+pub trait InputSource {
+    fn handle_event(&mut self, event: &Event) -> Vec<CommandEvent>;
+}
+
+/// Collects the command events of all sources and hands them out once per tick
+pub struct InputLayer {
+    sources: Vec<Box<dyn InputSource>>,
+    pending_commands: Vec<CommandEvent>,
+}
+
+impl InputLayer {
+    pub fn new(sources: Vec<Box<dyn InputSource>>) -> InputLayer {
+        InputLayer {
+            sources,
+            pending_commands: vec![],
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> PlayerCommands {
+        for source in self.sources.iter_mut() {
+            let commands = source.handle_event(event);
+            self.pending_commands.extend(commands);
+        }
+
+        if event.update_args().is_some() {
+            PlayerCommands(self.pending_commands.drain(..).collect())
+        } else {
+            PlayerCommands::default()
+        }
+    }
+}
+
+pub struct KeyboardInput {
+    bindings: HashMap<Button, (PlayerId, PlayerCommand)>,
+}
+
+impl KeyboardInput {
+    pub fn new(controls_config: &ControlsConfig, player_ids: &[PlayerId]) -> KeyboardInput {
+        KeyboardInput {
+            bindings: player_ids
+                .iter()
+                .flat_map(|player_id| {
+                    let profile = controls_config.get_profile(*player_id);
+                    COMMANDS
+                        .iter()
+                        .map(move |command| (profile.get_button(*command), (*player_id, *command)))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl InputSource for KeyboardInput {
+    fn handle_event(&mut self, event: &Event) -> Vec<CommandEvent> {
+        event
+            .button_args()
+            .and_then(|button_args| {
+                self.bindings
+                    .get(&button_args.button)
+                    .map(|(player_id, command)| {
+                        CommandEvent::new(*player_id, *command, button_args.state.into())
+                    })
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// The state manager polls the gamepads and passes their command events on in gamepad events,
+/// see `gamepads::create_event`
+pub struct GamepadInput;
+
+impl InputSource for GamepadInput {
+    fn handle_event(&mut self, event: &Event) -> Vec<CommandEvent> {
+        gamepads::get_commands(event).cloned().unwrap_or_default()
+    }
+}
+
+/// Commands are written by the names the controls screen shows, e.g. `command = "bomb"`
+mod command_format {
+    use crate::game_states::play_state::controls::{get_command_name, COMMANDS};
+    use crate::game_states::play_state::players::PlayerCommand;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        command: &PlayerCommand,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(get_command_name(*command))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PlayerCommand, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        COMMANDS
+            .iter()
+            .find(|command| get_command_name(**command) == name)
+            .copied()
+            .ok_or_else(|| D::Error::custom(format!("There is no command {}", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_states::play_state::players::Direction;
+    use piston::input::{ButtonArgs, Input, Key, Loop, UpdateArgs};
+
+    fn create_button_event(key: Key, state: ButtonState) -> Event {
+        Event::Input(
+            Input::Button(ButtonArgs {
+                state,
+                button: Button::Keyboard(key),
+                scancode: None,
+            }),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_keyboard_commands_are_handed_out_per_tick() {
+        let keyboard = KeyboardInput::new(
+            &ControlsConfig::default(),
+            &[PlayerId::Player1, PlayerId::Player2],
+        );
+        let mut input_layer = InputLayer::new(vec![Box::new(keyboard)]);
+        let update = Event::Loop(Loop::Update(UpdateArgs { dt: 0.1 }));
+
+        for (key, state) in &[
+            (Key::Left, ButtonState::Press),
+            (Key::LCtrl, ButtonState::Press),
+            (Key::NumPad0, ButtonState::Press),
+            (Key::Left, ButtonState::Release),
+        ] {
+            assert_eq!(
+                input_layer.handle_event(&create_button_event(*key, *state)),
+                PlayerCommands::default()
+            );
+        }
+
+        assert_eq!(
+            input_layer.handle_event(&update),
+            PlayerCommands(vec![
+                CommandEvent::new(
+                    PlayerId::Player1,
+                    PlayerCommand::Movement(Direction::Left),
+                    CommandState::Pressed
+                ),
+                CommandEvent::new(
+                    PlayerId::Player2,
+                    PlayerCommand::Bomb,
+                    CommandState::Pressed
+                ),
+                CommandEvent::new(
+                    PlayerId::Player1,
+                    PlayerCommand::Movement(Direction::Left),
+                    CommandState::Released
+                ),
+            ])
+        );
+        assert_eq!(input_layer.handle_event(&update), PlayerCommands::default());
+    }
+}
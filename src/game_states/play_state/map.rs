@@ -1,3 +1,5 @@
+use crate::game_states::play_state::collisions;
+use crate::game_states::play_state::grid::ArenaGrid;
 use crate::game_states::play_state::object_groups::{
     ArenaObjectGroup, PlayerSpawnsProperties, SoftBlockAreasProperties,
 };
@@ -12,10 +14,15 @@ use legion::world::World;
 use nalgebra::Vector2;
 use ncollide2d::shape::{Cuboid, ShapeHandle};
 use nphysics2d::object::{BodyPartHandle, BodyStatus, ColliderDesc, RigidBodyDesc};
+use rand::Rng;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use tiled::{Object, PropertyValue};
 
+pub const SOFT_BLOCK_BREAK_TILE_NAME: &str = "soft_block_break";
+pub const HARD_BLOCK_TILE_NAME: &str = "hard_block";
+const HARD_BLOCK_LAYER: usize = 1;
+
 pub struct Map {
     pub tilemap: Arc<Tilemap>,
     pub tile_animations: Arc<RwLock<HashMap<TileId, Arc<RwLock<Animation>>>>>,
@@ -72,8 +79,10 @@ impl Map {
             .iter()
             .enumerate()
             .flat_map(|(layer_index, layer)| {
+                // in a fixed order, so replays get the same entities
                 layer
                     .iter()
+                    .sorted_by_key(|(&[x, y], _)| (y, x))
                     .map(|(&[x, y], &tile_id)| {
                         let entity = self.create_tilemap_entity(
                             world,
@@ -99,20 +108,12 @@ impl Map {
             .collect_vec();
     }
 
-    pub fn create_soft_blocks(&mut self, world: &mut World, physics_world: &mut PhysicsWorld) {
-        let should_spawn_soft_block = |soft_block: &&Object| -> bool {
-            soft_block
-                .properties
-                .get(SoftBlockAreasProperties::SpawnChance.as_str())
-                .map(|property_value| match property_value {
-                    PropertyValue::FloatValue(spawn_chance) => {
-                        rand::random::<f32>() <= *spawn_chance
-                    }
-                    _ => false,
-                })
-                .unwrap_or(false)
-        };
-
+    pub fn create_soft_blocks(
+        &mut self,
+        world: &mut World,
+        physics_world: &mut PhysicsWorld,
+        rng: &mut impl Rng,
+    ) {
         let create_entity = |object: &Object| match object
             .properties
             .get(SoftBlockAreasProperties::RenderLayer.as_str())
@@ -136,13 +137,8 @@ impl Map {
             _ => None,
         };
 
-        self.soft_block_entities = self
-            .tilemap
-            .object_groups
-            .get(ArenaObjectGroup::SoftBlockAreas.as_str())
-            .iter()
-            .flat_map(|objects| objects.iter())
-            .filter(should_spawn_soft_block)
+        self.soft_block_entities = roll_soft_blocks(&self.tilemap, rng)
+            .into_iter()
             .filter_map(create_entity)
             .collect_vec();
     }
@@ -167,6 +163,45 @@ impl Map {
             .unwrap()
     }
 
+    /// Called when a soft block is broken or destroyed
+    pub fn remove_soft_block(&mut self, soft_block: Entity) {
+        self.soft_block_entities
+            .retain(|other_soft_block| *other_soft_block != soft_block);
+    }
+
+    /// Creates a hard block after the map has been loaded, e.g. during sudden death
+    pub fn create_hard_block(&mut self, world: &mut World, [x, y]: TilePosition) -> Option<Entity> {
+        let tile_id = self
+            .tilemap
+            .tileset
+            .get_tile_id_by_name(HARD_BLOCK_TILE_NAME)?;
+
+        let entity = self.create_tilemap_entity(
+            world,
+            components::EntityType::HardBlock,
+            HARD_BLOCK_LAYER,
+            tile_id,
+        );
+
+        let physical_components = {
+            let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+            self.try_creating_physical_components(
+                &mut physics_world,
+                entity,
+                components::EntityType::HardBlock,
+                tile_id,
+                x as f64,
+                y as f64,
+            )
+        };
+
+        self.add_physical_components(world, entity, physical_components, x as f64, y as f64);
+        self.try_adding_shared_animation_component(world, entity, tile_id);
+        self.tilemap_entities.push(entity);
+
+        Some(entity)
+    }
+
     fn try_adding_physical_component(
         &self,
         world: &mut World,
@@ -176,39 +211,85 @@ impl Map {
         x: f64,
         y: f64,
     ) {
-        if let Some(&[hx, hy, w, h]) = self.tilemap.tileset.hit_boxes.get(&tile_id) {
-            let half_tile_width = self.tilemap.tile_width as f64 / 2.0;
-            let half_tile_height = self.tilemap.tile_height as f64 / 2.0;
-
-            let body = RigidBodyDesc::new()
-                .translation(Vector2::new(x + half_tile_width, y + half_tile_height))
-                .status(BodyStatus::Static)
-                .gravity_enabled(false)
-                .user_data(entity)
-                .build();
-
-            let body_handle = physics_world.bodies.insert(body);
-
-            let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(Vector2::new(
-                w / 2.0,
-                h / 2.0,
-            ))))
-            .translation(Vector2::new(
-                hx - half_tile_width + w / 2.0,
-                hy - half_tile_height + h / 2.0,
-            ))
-            .user_data(entity)
-            .build(BodyPartHandle(body_handle, 0));
+        let entity_type = *world.get_tag::<components::EntityType>(entity).unwrap();
+        let physical_components = self.try_creating_physical_components(
+            physics_world,
+            entity,
+            entity_type,
+            tile_id,
+            x,
+            y,
+        );
+        self.add_physical_components(world, entity, physical_components, x, y);
+    }
 
-            let collider_handle = physics_world.colliders.insert(collider);
+    fn add_physical_components(
+        &self,
+        world: &mut World,
+        entity: Entity,
+        physical_components: Option<(components::BodyHandle, components::ColliderHandle)>,
+        x: f64,
+        y: f64,
+    ) {
+        if let Some((body_handle, collider_handle)) = physical_components {
+            world.add_component(entity, body_handle);
+            world.add_component(entity, collider_handle);
 
-            world.add_component(entity, components::BodyHandle(body_handle));
-            world.add_component(entity, components::ColliderHandle(collider_handle));
+            // only solid tiles take up space in the arena
+            let entity_type = *world.get_tag::<components::EntityType>(entity).unwrap();
+            world.resources.get_mut::<ArenaGrid>().unwrap().insert(
+                entity,
+                entity_type,
+                [x as u32, y as u32],
+            );
         } else {
             world.add_component(entity, components::ScreenPosition([x, y]));
         }
     }
 
+    fn try_creating_physical_components(
+        &self,
+        physics_world: &mut PhysicsWorld,
+        entity: Entity,
+        entity_type: components::EntityType,
+        tile_id: TileId,
+        x: f64,
+        y: f64,
+    ) -> Option<(components::BodyHandle, components::ColliderHandle)> {
+        let &[hx, hy, w, h] = self.tilemap.tileset.hit_boxes.get(&tile_id)?;
+
+        let half_tile_width = self.tilemap.tile_width as f64 / 2.0;
+        let half_tile_height = self.tilemap.tile_height as f64 / 2.0;
+
+        let body = RigidBodyDesc::new()
+            .translation(Vector2::new(x + half_tile_width, y + half_tile_height))
+            .status(BodyStatus::Static)
+            .gravity_enabled(false)
+            .user_data(entity)
+            .build();
+
+        let body_handle = physics_world.bodies.insert(body);
+
+        let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(Vector2::new(
+            w / 2.0,
+            h / 2.0,
+        ))))
+        .translation(Vector2::new(
+            hx - half_tile_width + w / 2.0,
+            hy - half_tile_height + h / 2.0,
+        ))
+        .collision_groups(collisions::get_collision_groups(entity_type))
+        .user_data(entity)
+        .build(BodyPartHandle(body_handle, 0));
+
+        let collider_handle = physics_world.colliders.insert(collider);
+
+        Some((
+            components::BodyHandle(body_handle),
+            components::ColliderHandle(collider_handle),
+        ))
+    }
+
     fn try_adding_shared_animation_component(
         &self,
         world: &mut World,
@@ -227,17 +308,155 @@ impl Map {
             .iter()
             .flat_map(|objects| objects.iter())
             .filter_map(|object| {
-                object
-                    .properties
-                    .get(PlayerSpawnsProperties::PlayerId.as_str())
-                    .and_then(|property_value| match property_value {
-                        PropertyValue::IntValue(player_id) => Some((
-                            PlayerId::from(player_id.abs() as u32),
-                            [object.x.abs() as u32, object.y.abs() as u32],
-                        )),
-                        _ => None,
-                    })
+                get_spawn_player_id(object)
+                    .map(|player_id| (player_id, [object.x.abs() as u32, object.y.abs() as u32]))
             })
             .collect()
     }
 }
+
+fn get_spawn_player_id(spawn: &Object) -> Option<PlayerId> {
+    match spawn
+        .properties
+        .get(PlayerSpawnsProperties::PlayerId.as_str())
+    {
+        Some(PropertyValue::IntValue(player_id)) => Some(PlayerId::from(player_id.abs() as u32)),
+        _ => None,
+    }
+}
+
+/// Fails if one of the players has no spawn among the objects of the player spawns group
+pub fn check_player_spawns(spawns: &[Object], player_ids: &[PlayerId]) -> Result<(), String> {
+    let missing_player_ids = player_ids
+        .iter()
+        .filter(|player_id| {
+            !spawns
+                .iter()
+                .any(|spawn| get_spawn_player_id(spawn) == Some(**player_id))
+        })
+        .map(|player_id| u32::from(*player_id))
+        .join(", ");
+
+    if missing_player_ids.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "There is no spawn for player {}",
+            missing_player_ids
+        ))
+    }
+}
+
+/// Rolls which soft block areas get a soft block, so the same seed always fills the same areas
+pub fn roll_soft_blocks<'a>(tilemap: &'a Tilemap, rng: &mut impl Rng) -> Vec<&'a Object> {
+    tilemap
+        .object_groups
+        .get(ArenaObjectGroup::SoftBlockAreas.as_str())
+        .iter()
+        .flat_map(|objects| objects.iter())
+        .filter(|soft_block| {
+            soft_block
+                .properties
+                .get(SoftBlockAreasProperties::SpawnChance.as_str())
+                .map(|property_value| match property_value {
+                    PropertyValue::FloatValue(spawn_chance) => rng.gen::<f32>() <= *spawn_chance,
+                    _ => false,
+                })
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub fn create_soft_block_area(id: u32, [x, y]: TilePosition, spawn_chance: f32) -> Object {
+    Object {
+        id,
+        gid: 1,
+        name: String::new(),
+        obj_type: String::new(),
+        x: x as f32,
+        y: y as f32,
+        rotation: 0.0,
+        visible: true,
+        shape: tiled::ObjectShape::Rect {
+            width: 32.0,
+            height: 32.0,
+        },
+        properties: vec![(
+            SoftBlockAreasProperties::SpawnChance.as_str().to_string(),
+            PropertyValue::FloatValue(spawn_chance),
+        )]
+        .into_iter()
+        .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn create_spawn(player_id: u32) -> Object {
+        Object {
+            id: player_id,
+            gid: 0,
+            name: String::new(),
+            obj_type: String::new(),
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            visible: true,
+            shape: tiled::ObjectShape::Rect {
+                width: 32.0,
+                height: 32.0,
+            },
+            properties: vec![(
+                PlayerSpawnsProperties::PlayerId.as_str().to_string(),
+                PropertyValue::IntValue(player_id as i32),
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_player_spawns() {
+        let spawns = [1, 2, 4]
+            .iter()
+            .map(|player_id| create_spawn(*player_id))
+            .collect_vec();
+
+        assert_eq!(
+            check_player_spawns(&spawns, &[PlayerId::Player1, PlayerId::Player4]),
+            Ok(())
+        );
+        assert_eq!(
+            check_player_spawns(&spawns[1..], &PlayerId::ALL),
+            Err("There is no spawn for player 1, 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_roll_soft_blocks() {
+        let mut area_without_chance = create_soft_block_area(3, [96, 0], 1.0);
+        area_without_chance.properties.clear();
+
+        let mut tilemap = Tilemap::with_size(4, 1);
+        tilemap.object_groups.insert(
+            ArenaObjectGroup::SoftBlockAreas.as_str().to_string(),
+            vec![
+                create_soft_block_area(0, [0, 0], 1.0),
+                create_soft_block_area(1, [32, 0], 0.0),
+                create_soft_block_area(2, [64, 0], 1.0),
+                area_without_chance,
+            ],
+        );
+
+        let soft_blocks = roll_soft_blocks(&tilemap, &mut StdRng::seed_from_u64(7))
+            .iter()
+            .map(|soft_block| soft_block.id)
+            .collect_vec();
+        assert_eq!(soft_blocks, vec![0, 2]);
+    }
+}
@@ -1,3 +1,7 @@
+use crate::game_states::play_state::bombs::BombType;
+use crate::game_states::play_state::collisions;
+use crate::game_states::play_state::grid::ArenaGrid;
+use crate::game_states::play_state::input::CommandState;
 use crate::game_states::play_state::{components, PhysicsWorld};
 use crate::tiles::animation::Animation;
 use crate::tiles::tilemap::Tilemap;
@@ -8,14 +12,82 @@ use legion::world::World;
 use nalgebra::Vector2;
 use ncollide2d::shape::{Cuboid, ShapeHandle};
 use nphysics2d::object::{BodyPartHandle, BodyStatus, ColliderDesc, RigidBodyDesc};
-use piston::input::{Button, Key};
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use tiled::PropertyValue;
 
+pub const DEATH_TILE_NAME: &str = "death";
+pub const INVULNERABILITY_DURATION: f64 = 2.0;
+pub const BLINK_INTERVAL: f64 = 0.1;
+/// How far a player has to be off the center of a lane, in tiles,
+/// to be slid around the corner of a blocked lane
+pub const CORNER_SLIDE_THRESHOLD: f64 = 0.25;
+/// How close a player stopped by an obstacle has to get to the center of their tile,
+/// in pixels, to count as having walked up to it, e.g. to kick a bomb
+pub const WALK_UP_TOLERANCE: f64 = 1.0;
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 4;
+
 pub struct Players {
     pub players: Vec<Entity>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stat<T> {
+    pub default: T,
+    pub min: T,
+    pub max: T,
+}
+
+impl<T: PartialOrd + Copy> Stat<T> {
+    pub fn clamp(&self, value: T) -> T {
+        if value < self.min {
+            self.min
+        } else if value > self.max {
+            self.max
+        } else {
+            value
+        }
+    }
+}
+
+/// Starting values and limits of the player stats.
+/// Power ups change the stats of a player only within these limits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerStats {
+    pub movement_speed: Stat<f64>,
+    pub bomb_capacity: Stat<u32>,
+    pub blast_range: Stat<u32>,
+    pub lives: Stat<u32>,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        PlayerStats {
+            movement_speed: Stat {
+                default: 3.0,
+                min: 2.0,
+                max: 6.0,
+            },
+            bomb_capacity: Stat {
+                default: 1,
+                min: 1,
+                max: 8,
+            },
+            blast_range: Stat {
+                default: 2,
+                min: 1,
+                max: 8,
+            },
+            lives: Stat {
+                default: 3,
+                min: 0,
+                max: 9,
+            },
+        }
+    }
+}
+
 impl Players {
     pub fn new() -> Players {
         Players { players: vec![] }
@@ -24,13 +96,19 @@ impl Players {
     pub fn create_player(
         &mut self,
         id: PlayerId,
-        player_spawns: &HashMap<PlayerId, TilePosition>,
+        position: TilePosition,
         asset_storage: &AssetStorage,
-        tilemap: &Tilemap,
         world: &mut World,
         physics_world: &mut PhysicsWorld,
     ) {
         let tileset = asset_storage.get_asset::<Tileset>(id.as_str());
+        let stats = *world.resources.get::<PlayerStats>().unwrap();
+        let tilemap = world
+            .resources
+            .get::<components::Tilemap>()
+            .unwrap()
+            .0
+            .clone();
         let tile_id = PlayerFaceDirection::Down.get_tile_id(&tileset).unwrap();
 
         let player = world
@@ -45,8 +123,11 @@ impl Players {
                     components::CurrentTileId(tile_id),
                     components::Tileset(tileset.clone()),
                     components::MoveDirectionStack(vec![]),
-                    components::MovementSpeed(1.0),
-                    Self::create_player_controls(id),
+                    components::MovementSpeed(stats.movement_speed.default),
+                    components::BombCapacity(stats.bomb_capacity.default),
+                    components::BlastRange(stats.blast_range.default),
+                    components::BombVariant(BombType::Normal),
+                    components::Lives(stats.lives.default),
                     components::DeactivatedCommands(HashSet::new()),
                     components::AnimationType::Ownd(
                         tileset
@@ -67,20 +148,33 @@ impl Players {
             .copied()
             .unwrap();
 
-        let position = *player_spawns.get(&id).unwrap();
         let hit_box = tileset.hit_boxes[&tile_id];
-        Self::add_physical_components(world, physics_world, player, tilemap, position, hit_box);
+        let (body_handle, collider_handle, touch_sensor_handle) =
+            Self::create_body(physics_world, player, &tilemap, position, hit_box);
+
+        world.add_component(player, components::SpawnPosition(position));
+        world.add_component(player, body_handle);
+        world.add_component(player, collider_handle);
+        world.add_component(player, touch_sensor_handle);
+        world.resources.get_mut::<ArenaGrid>().unwrap().insert(
+            player,
+            components::EntityType::Player,
+            position,
+        );
 
         self.players.push(player);
     }
 
-    fn add_physical_components(
-        world: &mut World,
+    pub fn create_body(
         physics_world: &mut PhysicsWorld,
         entity: Entity,
         tilemap: &Tilemap,
         pos: [u32; 2],
         hit_box: HitBox,
+    ) -> (
+        components::BodyHandle,
+        components::ColliderHandle,
+        components::TouchSensorHandle,
     ) {
         let [x, y] = pos;
         let [hx, hy, w, h] = hit_box;
@@ -90,7 +184,6 @@ impl Players {
 
         let body = RigidBodyDesc::new()
             .status(BodyStatus::Dynamic)
-            .linear_damping(5.0)
             .mass(1.0)
             .translation(Vector2::new(
                 x as f64 + half_tile_width,
@@ -109,66 +202,186 @@ impl Players {
             hx - half_tile_width + w / 2.0,
             hy - half_tile_height + h / 2.0,
         ))
+        .collision_groups(collisions::get_collision_groups(
+            components::EntityType::Player,
+        ))
         .user_data(entity)
         .build(BodyPartHandle(body_handle, 0));
 
         let collider_handle = physics_world.colliders.insert(collider);
 
-        world.add_component(entity, components::BodyHandle(body_handle));
-        world.add_component(entity, components::ColliderHandle(collider_handle));
+        // players don't collide with each other, but still have to know when they touch
+        let touch_sensor = ColliderDesc::new(ShapeHandle::new(Cuboid::new(Vector2::new(
+            w / 2.0,
+            h / 2.0,
+        ))))
+        .translation(Vector2::new(
+            hx - half_tile_width + w / 2.0,
+            hy - half_tile_height + h / 2.0,
+        ))
+        .sensor(true)
+        .collision_groups(collisions::get_player_touch_collision_groups())
+        .user_data(entity)
+        .build(BodyPartHandle(body_handle, 0));
+        let touch_sensor_handle = physics_world.colliders.insert(touch_sensor);
+
+        (
+            components::BodyHandle(body_handle),
+            components::ColliderHandle(collider_handle),
+            components::TouchSensorHandle(touch_sensor_handle),
+        )
     }
+}
 
-    fn create_player_controls(player_id: PlayerId) -> components::Controls {
-        let mut controls = HashMap::new();
+/// How a player moves along the lanes of the grid during one update
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LaneMovement {
+    /// In pixels per second
+    pub velocity: Vector2<f64>,
+    /// Whether the player has walked up to the blocked tile in front of them
+    pub is_facing_obstacle: bool,
+}
 
-        match player_id {
-            PlayerId::Player1 => {
-                controls.insert(
-                    Button::Keyboard(Key::Left),
-                    PlayerCommand::Movement(Direction::Left),
-                );
-                controls.insert(
-                    Button::Keyboard(Key::Right),
-                    PlayerCommand::Movement(Direction::Right),
-                );
-                controls.insert(
-                    Button::Keyboard(Key::Up),
-                    PlayerCommand::Movement(Direction::Up),
-                );
-                controls.insert(
-                    Button::Keyboard(Key::Down),
-                    PlayerCommand::Movement(Direction::Down),
-                );
-                controls.insert(Button::Keyboard(Key::RCtrl), PlayerCommand::Bomb);
-            }
-            PlayerId::Player2 => {
-                controls.insert(
-                    Button::Keyboard(Key::A),
-                    PlayerCommand::Movement(Direction::Left),
-                );
-                controls.insert(
-                    Button::Keyboard(Key::D),
-                    PlayerCommand::Movement(Direction::Right),
-                );
-                controls.insert(
-                    Button::Keyboard(Key::W),
-                    PlayerCommand::Movement(Direction::Up),
-                );
-                controls.insert(
-                    Button::Keyboard(Key::S),
-                    PlayerCommand::Movement(Direction::Down),
-                );
-                controls.insert(Button::Keyboard(Key::LCtrl), PlayerCommand::Bomb);
+/// Keeps a player on the lanes of the grid and slides them around corners
+/// when they are only slightly off an open lane.
+/// `offset` is the position of the player relative to the center of their tile, and
+/// `is_free` tells whether the tile at a `[columns, rows]` offset from it can be entered.
+/// Velocities are chosen so that the player doesn't overshoot the center of a lane.
+pub fn get_lane_movement(
+    offset: Vector2<f64>,
+    direction: Direction,
+    speed: f64,
+    tile_size: f64,
+    dt: f64,
+    is_free: impl Fn([i32; 2]) -> bool,
+) -> LaneMovement {
+    let [dx, dy] = direction.get_tile_offset();
+    let forward = Vector2::new(dx as f64, dy as f64);
+    let sideways = Vector2::new(dy.abs() as f64, dx.abs() as f64);
+
+    let distance_past_center = offset.dot(&forward);
+    let lane_offset = offset.dot(&sideways);
+    let clamp_speed = |distance: f64| (distance / dt).max(-speed).min(speed);
+
+    if is_free([dx, dy]) {
+        return LaneMovement {
+            velocity: forward * speed + sideways * clamp_speed(-lane_offset),
+            is_facing_obstacle: false,
+        };
+    }
+
+    let side = if lane_offset > 0.0 { 1 } else { -1 };
+    let [side_x, side_y] = [dy.abs() * side, dx.abs() * side];
+    let can_slide_around_corner = lane_offset.abs() >= CORNER_SLIDE_THRESHOLD * tile_size
+        && is_free([side_x, side_y])
+        && is_free([side_x + dx, side_y + dy]);
+
+    if can_slide_around_corner {
+        LaneMovement {
+            velocity: sideways * speed * side as f64,
+            is_facing_obstacle: false,
+        }
+    } else {
+        LaneMovement {
+            velocity: forward * clamp_speed(-distance_past_center).max(0.0)
+                + sideways * clamp_speed(-lane_offset),
+            is_facing_obstacle: distance_past_center > -WALK_UP_TOLERANCE,
+        }
+    }
+}
+
+/// Which players take part in a match
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerSetup {
+    player_ids: Vec<PlayerId>,
+}
+
+impl PlayerSetup {
+    pub fn new(player_count: usize) -> Result<PlayerSetup, String> {
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&player_count) {
+            return Err(format!(
+                "The player count has to be between {} and {}, but is {}",
+                MIN_PLAYERS, MAX_PLAYERS, player_count
+            ));
+        }
+
+        Ok(PlayerSetup {
+            player_ids: PlayerId::ALL[..player_count].to_vec(),
+        })
+    }
+
+    /// Leaves a player out of the match, e.g. when there is no input device for them.
+    /// The following players keep their ids, so they still use their own spawn and controls.
+    pub fn skip(mut self, player_id: PlayerId) -> Result<PlayerSetup, String> {
+        if !self.player_ids.contains(&player_id) {
+            return Err(format!(
+                "Player {} is not part of the match",
+                u32::from(player_id)
+            ));
+        }
+
+        if self.player_ids.len() <= MIN_PLAYERS {
+            return Err(format!(
+                "Cannot skip player {}, at least {} players are needed",
+                u32::from(player_id),
+                MIN_PLAYERS
+            ));
+        }
+
+        self.player_ids.retain(|other_id| *other_id != player_id);
+        Ok(self)
+    }
+
+    /// Reads `--players <count>` and any number of `--skip <player>` from the command line
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<PlayerSetup, String> {
+        let mut player_count = MIN_PLAYERS;
+        let mut skipped_players = vec![];
+
+        while let Some(arg) = args.next() {
+            let value = match arg.as_str() {
+                "--players" | "--skip" => args
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .ok_or_else(|| format!("{} expects a number", arg))?,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            };
+
+            if arg == "--players" {
+                player_count = value;
+            } else if (1..=MAX_PLAYERS).contains(&value) {
+                skipped_players.push(PlayerId::from(value as u32));
+            } else {
+                return Err(format!("There is no player {}", value));
             }
-            PlayerId::Player3 => {}
-            PlayerId::Player4 => {}
         }
 
-        components::Controls(controls)
+        skipped_players
+            .into_iter()
+            .try_fold(PlayerSetup::new(player_count)?, PlayerSetup::skip)
+    }
+
+    /// Rebuilds a setup from its player ids, e.g. the ones stored in a replay
+    pub fn from_player_ids(player_ids: &[PlayerId]) -> Result<PlayerSetup, String> {
+        PlayerId::ALL
+            .iter()
+            .filter(|player_id| !player_ids.contains(player_id))
+            .try_fold(PlayerSetup::new(MAX_PLAYERS)?, |player_setup, player_id| {
+                player_setup.skip(*player_id)
+            })
+    }
+
+    pub fn get_player_ids(&self) -> &[PlayerId] {
+        &self.player_ids
     }
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+impl Default for PlayerSetup {
+    fn default() -> Self {
+        PlayerSetup::new(MIN_PLAYERS).unwrap()
+    }
+}
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum PlayerId {
     Player1,
     Player2,
@@ -177,6 +390,13 @@ pub enum PlayerId {
 }
 
 impl PlayerId {
+    pub const ALL: [PlayerId; MAX_PLAYERS] = [
+        PlayerId::Player1,
+        PlayerId::Player2,
+        PlayerId::Player3,
+        PlayerId::Player4,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         self.into()
     }
@@ -193,6 +413,17 @@ impl From<&PlayerId> for &str {
     }
 }
 
+impl From<PlayerId> for u32 {
+    fn from(player_id: PlayerId) -> Self {
+        match player_id {
+            PlayerId::Player1 => 1,
+            PlayerId::Player2 => 2,
+            PlayerId::Player3 => 3,
+            PlayerId::Player4 => 4,
+        }
+    }
+}
+
 impl From<u32> for PlayerId {
     fn from(num: u32) -> Self {
         match num {
@@ -229,6 +460,15 @@ impl PlayerFaceDirection {
             .map(|(tile_id, _)| *tile_id)
     }
 
+    pub fn from_tile_id(tileset: &Tileset, tile_id: TileId) -> Option<PlayerFaceDirection> {
+        match tileset.properties.get(&tile_id)?.get("face_direction") {
+            Some(PropertyValue::StringValue(face_direction)) => {
+                Some(PlayerFaceDirection::from(face_direction.as_str()))
+            }
+            _ => None,
+        }
+    }
+
     pub fn as_str(&self) -> &str {
         self.into()
     }
@@ -260,6 +500,17 @@ impl From<Direction> for PlayerFaceDirection {
     }
 }
 
+impl From<PlayerFaceDirection> for Direction {
+    fn from(player_face_direction: PlayerFaceDirection) -> Self {
+        match player_face_direction {
+            PlayerFaceDirection::Down => Direction::Down,
+            PlayerFaceDirection::Up => Direction::Up,
+            PlayerFaceDirection::Left => Direction::Left,
+            PlayerFaceDirection::Right => Direction::Right,
+        }
+    }
+}
+
 impl From<&PlayerFaceDirection> for &str {
     fn from(player_face_direction: &PlayerFaceDirection) -> Self {
         match player_face_direction {
@@ -275,6 +526,7 @@ impl From<&PlayerFaceDirection> for &str {
 pub enum PlayerCommand {
     Movement(Direction),
     Bomb,
+    Detonate,
 }
 
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
@@ -284,3 +536,119 @@ pub enum Direction {
     Left,
     Right,
 }
+
+impl Direction {
+    pub fn get_opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    pub fn get_tile_offset(&self) -> [i32; 2] {
+        match self {
+            Direction::Up => [0, -1],
+            Direction::Down => [0, 1],
+            Direction::Left => [-1, 0],
+            Direction::Right => [1, 0],
+        }
+    }
+}
+
+/// The latest pressed direction, which is still held, moves the player
+pub fn update_move_direction_stack(
+    move_direction_stack: &mut Vec<Direction>,
+    direction: Direction,
+    state: CommandState,
+) {
+    match state {
+        CommandState::Pressed => move_direction_stack.push(direction),
+        CommandState::Released => {
+            if let Some(index) = move_direction_stack
+                .iter()
+                .position(|stored_direction| *stored_direction == direction)
+            {
+                move_direction_stack.remove(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lane_movement() {
+        let get_movement = |offset: [f64; 2], blocked_tiles: &[[i32; 2]]| {
+            get_lane_movement(
+                Vector2::new(offset[0], offset[1]),
+                Direction::Right,
+                64.0,
+                32.0,
+                0.1,
+                |tile| !blocked_tiles.contains(&tile),
+            )
+        };
+
+        // aligned with the lane
+        assert_eq!(
+            get_movement([-4.0, 0.0], &[]),
+            LaneMovement {
+                velocity: Vector2::new(64.0, 0.0),
+                is_facing_obstacle: false,
+            }
+        );
+
+        // slightly off the lane, which is pulled back without overshooting its center
+        assert_eq!(
+            get_movement([0.0, 2.0], &[]).velocity,
+            Vector2::new(64.0, -20.0)
+        );
+        assert_eq!(
+            get_movement([0.0, 2.0], &[[1, 0]]),
+            LaneMovement {
+                velocity: Vector2::new(0.0, -20.0),
+                is_facing_obstacle: true,
+            }
+        );
+
+        // far enough off a blocked lane to slide around the corner below it
+        assert_eq!(
+            get_movement([0.0, 10.0], &[[1, 0]]).velocity,
+            Vector2::new(0.0, 64.0)
+        );
+
+        // unless that corner is blocked as well
+        assert_eq!(
+            get_movement([-10.0, 10.0], &[[1, 0], [1, 1]]),
+            LaneMovement {
+                velocity: Vector2::new(64.0, -64.0),
+                is_facing_obstacle: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_player_setup_from_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            PlayerSetup::from_args(args(&[]).into_iter()),
+            Ok(PlayerSetup::default())
+        );
+        assert_eq!(
+            PlayerSetup::from_args(args(&["--players", "4", "--skip", "2"]).into_iter())
+                .unwrap()
+                .get_player_ids(),
+            &[PlayerId::Player1, PlayerId::Player3, PlayerId::Player4]
+        );
+        assert!(PlayerSetup::from_args(args(&["--players", "5"]).into_iter()).is_err());
+        assert!(PlayerSetup::from_args(args(&["--skip", "1"]).into_iter()).is_err());
+        assert!(
+            PlayerSetup::from_args(args(&["--players", "3", "--skip", "4"]).into_iter()).is_err()
+        );
+    }
+}
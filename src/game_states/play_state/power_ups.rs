@@ -0,0 +1,209 @@
+use crate::tiles::tilemap::Tilemap;
+use crate::tiles::tileset::{TileId, Tileset};
+use rand::Rng;
+use tiled::PropertyValue;
+
+pub const POWER_UPS_TILESET_ID: &str = "power_ups";
+/// In tiles per second
+pub const MOVEMENT_SPEED_STEP: f64 = 0.5;
+
+pub enum PowerUpProperties {
+    PowerUp,
+}
+
+impl PowerUpProperties {
+    pub fn as_str(&self) -> &str {
+        match self {
+            PowerUpProperties::PowerUp => "power_up",
+        }
+    }
+}
+
+pub enum PowerUpDropProperties {
+    DropChance,
+    Weight(PowerUpType),
+}
+
+impl PowerUpDropProperties {
+    pub fn get_name(&self) -> String {
+        match self {
+            PowerUpDropProperties::DropChance => "power_up_drop_chance".to_string(),
+            PowerUpDropProperties::Weight(power_up_type) => {
+                format!("{}_weight", power_up_type.as_str())
+            }
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum PowerUpType {
+    BombUp,
+    BombDown,
+    FireUp,
+    FireDown,
+    SpeedUp,
+    SpeedDown,
+    LifeUp,
+    BombKick,
+    RemoteControl,
+    PierceBomb,
+    PowerBomb,
+    LineBomb,
+    PowerGlove,
+    Skull,
+}
+
+impl PowerUpType {
+    pub const ALL: [PowerUpType; 14] = [
+        PowerUpType::BombUp,
+        PowerUpType::BombDown,
+        PowerUpType::FireUp,
+        PowerUpType::FireDown,
+        PowerUpType::SpeedUp,
+        PowerUpType::SpeedDown,
+        PowerUpType::LifeUp,
+        PowerUpType::BombKick,
+        PowerUpType::RemoteControl,
+        PowerUpType::PierceBomb,
+        PowerUpType::PowerBomb,
+        PowerUpType::LineBomb,
+        PowerUpType::PowerGlove,
+        PowerUpType::Skull,
+    ];
+
+    pub fn get_tile_id(&self, tileset: &Tileset) -> Option<TileId> {
+        tileset
+            .properties
+            .iter()
+            .find(|(_tile_id, properties)| {
+                match properties.get(PowerUpProperties::PowerUp.as_str()) {
+                    Some(PropertyValue::StringValue(power_up)) => {
+                        self.as_str() == power_up.as_str()
+                    }
+                    _ => false,
+                }
+            })
+            .map(|(tile_id, _)| *tile_id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            PowerUpType::BombUp => "bomb_up",
+            PowerUpType::BombDown => "bomb_down",
+            PowerUpType::FireUp => "fire_up",
+            PowerUpType::FireDown => "fire_down",
+            PowerUpType::SpeedUp => "speed_up",
+            PowerUpType::SpeedDown => "speed_down",
+            PowerUpType::LifeUp => "life_up",
+            PowerUpType::BombKick => "bomb_kick",
+            PowerUpType::RemoteControl => "remote_control",
+            PowerUpType::PierceBomb => "pierce_bomb",
+            PowerUpType::PowerBomb => "power_bomb",
+            PowerUpType::LineBomb => "line_bomb",
+            PowerUpType::PowerGlove => "power_glove",
+            PowerUpType::Skull => "skull",
+        }
+    }
+}
+
+/// The chance of a destroyed soft block to drop a power up and the weights
+/// of the different power ups, which are configured in the map properties.
+pub struct PowerUpDrops {
+    drop_chance: f32,
+    weights: Vec<(PowerUpType, f32)>,
+}
+
+impl PowerUpDrops {
+    pub fn from_tilemap(tilemap: &Tilemap) -> PowerUpDrops {
+        let get_float_property = |name: String| match tilemap.properties.get(&name) {
+            Some(PropertyValue::FloatValue(value)) => value.max(0.0),
+            _ => 0.0,
+        };
+
+        PowerUpDrops {
+            drop_chance: get_float_property(PowerUpDropProperties::DropChance.get_name()),
+            weights: PowerUpType::ALL
+                .iter()
+                .map(|power_up_type| {
+                    let weight = get_float_property(
+                        PowerUpDropProperties::Weight(*power_up_type).get_name(),
+                    );
+                    (*power_up_type, weight)
+                })
+                .filter(|(_, weight)| *weight > 0.0)
+                .collect(),
+        }
+    }
+
+    pub fn roll(&self, rng: &mut impl Rng) -> Option<PowerUpType> {
+        if rng.gen::<f32>() >= self.drop_chance {
+            return None;
+        }
+
+        let total_weight: f32 = self.weights.iter().map(|(_, weight)| weight).sum();
+        let mut roll = rng.gen::<f32>() * total_weight;
+
+        self.weights.iter().find_map(|(power_up_type, weight)| {
+            if roll < *weight {
+                Some(*power_up_type)
+            } else {
+                roll -= weight;
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+pub fn create_drop_properties(
+    drop_chance: f32,
+    weights: &[(PowerUpType, f32)],
+) -> tiled::Properties {
+    weights
+        .iter()
+        .map(|(power_up_type, weight)| (PowerUpDropProperties::Weight(*power_up_type), *weight))
+        .chain(std::iter::once((
+            PowerUpDropProperties::DropChance,
+            drop_chance,
+        )))
+        .map(|(property, value)| (property.get_name(), PropertyValue::FloatValue(value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn roll(drop_chance: f32, weights: &[(PowerUpType, f32)]) -> Vec<Option<PowerUpType>> {
+        let mut tilemap = Tilemap::with_size(0, 0);
+        tilemap.properties = create_drop_properties(drop_chance, weights);
+        let power_up_drops = PowerUpDrops::from_tilemap(&tilemap);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..50).map(|_| power_up_drops.roll(&mut rng)).collect()
+    }
+
+    #[test]
+    fn test_power_up_drops() {
+        assert!(roll(0.0, &[(PowerUpType::BombUp, 1.0)])
+            .iter()
+            .all(Option::is_none));
+        assert!(roll(
+            1.0,
+            &[(PowerUpType::BombUp, 1.0), (PowerUpType::Skull, 0.0)]
+        )
+        .iter()
+        .all(|drop| *drop == Some(PowerUpType::BombUp)));
+
+        let drops = roll(
+            0.5,
+            &[(PowerUpType::FireUp, 2.0), (PowerUpType::Skull, 1.0)],
+        );
+        assert!(drops.contains(&None));
+        assert!(drops.contains(&Some(PowerUpType::FireUp)));
+        assert!(drops.contains(&Some(PowerUpType::Skull)));
+        assert!(!drops.contains(&Some(PowerUpType::LifeUp)));
+    }
+}
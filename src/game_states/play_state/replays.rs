@@ -0,0 +1,416 @@
+use crate::game_states::play_state::input::{CommandEvent, InputSource, PlayerCommands};
+use crate::game_states::play_state::players::{PlayerId, PlayerSetup};
+use crate::game_states::play_state::round;
+use crate::game_states::play_state::TILEMAP_ID;
+use piston::input::{Event, UpdateEvent};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const REPLAYS_DIRECTORY: &str = "replays";
+/// How often a recording is written to its file while it grows, in seconds
+const AUTOSAVE_INTERVAL: f64 = 10.0;
+
+/// The command events of one tick, counted from the start of its round
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayTick {
+    pub round: u32,
+    pub tick: u64,
+    pub commands: Vec<CommandEvent>,
+}
+
+/// Everything needed to simulate a match again.
+/// Every round seeds its own RNG from the match seed, and only ticks with commands are stored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub map_id: String,
+    pub best_of: u32,
+    /// In seconds
+    pub tick_duration: f64,
+    pub player_ids: Vec<PlayerId>,
+    pub ticks: Vec<ReplayTick>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, player_setup: &PlayerSetup, best_of: u32, tick_duration: f64) -> Replay {
+        Replay {
+            seed,
+            map_id: TILEMAP_ID.to_string(),
+            best_of,
+            tick_duration,
+            player_ids: player_setup.get_player_ids().to_vec(),
+            ticks: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read {}: {}", path.display(), error))?;
+        Self::from_toml(&content).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Replay, String> {
+        let replay: Replay = toml::from_str(content).map_err(|error| error.to_string())?;
+
+        if replay.map_id != TILEMAP_ID {
+            return Err(format!("There is no map {}", replay.map_id));
+        }
+        round::validate_best_of(replay.best_of)?;
+        if replay.tick_duration <= 0.0 {
+            return Err(format!(
+                "The tick duration has to be positive, but is {}",
+                replay.tick_duration
+            ));
+        }
+
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .map_err(|error| format!("Cannot create {}: {}", directory.display(), error))?;
+        }
+
+        let content = toml::to_string(self).map_err(|error| error.to_string())?;
+        std::fs::write(path, content)
+            .map_err(|error| format!("Cannot write {}: {}", path.display(), error))
+    }
+
+    pub fn get_player_setup(&self) -> Result<PlayerSetup, String> {
+        PlayerSetup::from_player_ids(&self.player_ids)
+    }
+
+    /// The event loop has to update at this rate to reproduce the recorded tick duration
+    pub fn get_updates_per_second(&self) -> u64 {
+        (1.0 / self.tick_duration).round() as u64
+    }
+}
+
+enum ReplayMode {
+    Recording(PathBuf),
+    Playing,
+}
+
+/// Either records the current match or plays a replay back.
+/// It outlives the rounds, so it is shared with the state manager.
+/// A recording is saved after every round, every `AUTOSAVE_INTERVAL` seconds and
+/// when the game shuts down, so quitting in the middle of a match still leaves a replay.
+/// Ticks which couldn't be saved stay unsaved, so the next save tries again.
+pub struct Replays {
+    replay: Replay,
+    mode: ReplayMode,
+    match_rng: StdRng,
+    round: u32,
+    tick: u64,
+    next_tick_index: usize,
+    has_unsaved_ticks: bool,
+    /// In seconds
+    time_since_save: f64,
+}
+
+impl Replays {
+    pub fn record(player_setup: &PlayerSetup, best_of: u32, tick_duration: f64) -> Replays {
+        Replays::new(
+            Replay::new(rand::random(), player_setup, best_of, tick_duration),
+            ReplayMode::Recording(Self::create_path()),
+        )
+    }
+
+    pub fn play(replay: Replay) -> Replays {
+        Replays::new(replay, ReplayMode::Playing)
+    }
+
+    fn new(replay: Replay, mode: ReplayMode) -> Replays {
+        Replays {
+            match_rng: StdRng::seed_from_u64(replay.seed),
+            replay,
+            mode,
+            round: 0,
+            tick: 0,
+            next_tick_index: 0,
+            has_unsaved_ticks: false,
+            time_since_save: 0.0,
+        }
+    }
+
+    fn create_path() -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+
+        Path::new(REPLAYS_DIRECTORY).join(format!("match_{}.toml", timestamp))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self.mode {
+            ReplayMode::Recording(_) => false,
+            ReplayMode::Playing => true,
+        }
+    }
+
+    /// Returns the seed for the RNG of the new round
+    pub fn start_round(&mut self) -> u64 {
+        self.round += 1;
+        self.tick = 0;
+        self.match_rng.gen()
+    }
+
+    /// The recorded command events of the current tick
+    fn get_commands(&mut self) -> Vec<CommandEvent> {
+        let current_tick = (self.round, self.tick);
+
+        while let Some(replay_tick) = self.replay.ticks.get(self.next_tick_index) {
+            let tick = (replay_tick.round, replay_tick.tick);
+            if tick > current_tick {
+                break;
+            }
+
+            self.next_tick_index += 1;
+            if tick == current_tick {
+                return replay_tick.commands.clone();
+            }
+        }
+
+        vec![]
+    }
+
+    /// Has to be called once per tick, after the command events of the tick were handed out
+    pub fn add_tick(&mut self, player_commands: &PlayerCommands) {
+        if let ReplayMode::Recording(_) = self.mode {
+            if !player_commands.0.is_empty() {
+                self.replay.ticks.push(ReplayTick {
+                    round: self.round,
+                    tick: self.tick,
+                    commands: player_commands.0.clone(),
+                });
+                self.has_unsaved_ticks = true;
+            }
+        }
+
+        self.tick += 1;
+        self.time_since_save += self.replay.tick_duration;
+
+        if self.time_since_save >= AUTOSAVE_INTERVAL {
+            // a failed autosave is reported by the save at the end of the round
+            self.flush().ok();
+        }
+    }
+
+    /// A recording of a finished match is replaced by a new one, even if it couldn't be saved
+    pub fn end_round(&mut self, is_match_over: bool) -> Result<(), String> {
+        let mut result = Ok(());
+
+        if let ReplayMode::Recording(_) = self.mode {
+            result = self.save();
+
+            if is_match_over {
+                let replay = Replay {
+                    seed: rand::random(),
+                    ticks: vec![],
+                    ..self.replay.clone()
+                };
+                *self = Replays::new(replay, ReplayMode::Recording(Self::create_path()));
+            }
+        }
+
+        result
+    }
+
+    /// Saves the ticks recorded since the last save, e.g. when the game shuts down
+    pub fn flush(&mut self) -> Result<(), String> {
+        if self.has_unsaved_ticks {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn save(&mut self) -> Result<(), String> {
+        if let ReplayMode::Recording(path) = &self.mode {
+            self.replay.save(path)?;
+            self.has_unsaved_ticks = false;
+            self.time_since_save = 0.0;
+        }
+
+        Ok(())
+    }
+}
+
+/// Drives the players with the command events of a replay instead of any input device
+pub struct ReplayInput {
+    replays: Arc<RwLock<Replays>>,
+}
+
+impl ReplayInput {
+    pub fn new(replays: Arc<RwLock<Replays>>) -> ReplayInput {
+        ReplayInput { replays }
+    }
+}
+
+impl InputSource for ReplayInput {
+    fn handle_event(&mut self, event: &Event) -> Vec<CommandEvent> {
+        if event.update_args().is_some() {
+            self.replays.write().unwrap().get_commands()
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_states::play_state::diseases::Disease;
+    use crate::game_states::play_state::input::{CommandState, InputLayer};
+    use crate::game_states::play_state::map;
+    use crate::game_states::play_state::object_groups::ArenaObjectGroup;
+    use crate::game_states::play_state::players::{Direction, PlayerCommand};
+    use crate::game_states::play_state::power_ups::{self, PowerUpDrops, PowerUpType};
+    use crate::tiles::tilemap::Tilemap;
+    use piston::input::{Loop, UpdateArgs};
+
+    #[test]
+    fn test_replay_plays_back_recorded_ticks() {
+        let player_setup = PlayerSetup::new(3)
+            .unwrap()
+            .skip(PlayerId::Player2)
+            .unwrap();
+        let path = std::env::temp_dir().join("bomberman_test_replay.toml");
+        let mut recording = Replays::new(
+            Replay::new(42, &player_setup, 5, 1.0 / 120.0),
+            ReplayMode::Recording(path.clone()),
+        );
+
+        let recorded_ticks = vec![
+            (1, 2, PlayerCommand::Movement(Direction::Left)),
+            (1, 5, PlayerCommand::Bomb),
+            (2, 0, PlayerCommand::Detonate),
+        ];
+        let mut round_seeds = vec![];
+        for round in 1..=2 {
+            round_seeds.push(recording.start_round());
+            for tick in 0..8 {
+                let commands = recorded_ticks
+                    .iter()
+                    .filter(|(other_round, other_tick, _)| {
+                        (*other_round, *other_tick) == (round, tick)
+                    })
+                    .map(|(_, _, command)| {
+                        CommandEvent::new(PlayerId::Player3, *command, CommandState::Pressed)
+                    })
+                    .collect();
+                recording.add_tick(&PlayerCommands(commands));
+            }
+        }
+        assert_eq!(recording.replay.ticks.len(), 3);
+
+        // the round hasn't ended, so only flushing saves the recording
+        assert!(!path.exists());
+        assert_eq!(recording.flush(), Ok(()));
+        assert!(!recording.has_unsaved_ticks);
+        let recorded_replay = recording.replay.clone();
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay, recorded_replay);
+        assert_eq!(replay.get_player_setup(), Ok(player_setup));
+        assert_eq!(replay.get_updates_per_second(), 120);
+
+        let replays = Arc::new(RwLock::new(Replays::play(replay)));
+        let mut input_layer = InputLayer::new(vec![Box::new(ReplayInput::new(replays.clone()))]);
+        let update = Event::Loop(Loop::Update(UpdateArgs { dt: 1.0 / 120.0 }));
+
+        let mut played_ticks = vec![];
+        for round in 1..=2 {
+            assert_eq!(
+                replays.write().unwrap().start_round(),
+                round_seeds[round as usize - 1]
+            );
+            for tick in 0..8 {
+                let player_commands = input_layer.handle_event(&update);
+                replays.write().unwrap().add_tick(&player_commands);
+                played_ticks.extend(
+                    player_commands
+                        .0
+                        .iter()
+                        .map(|command_event| (round, tick, command_event.command)),
+                );
+            }
+        }
+        assert_eq!(played_ticks, recorded_ticks);
+    }
+
+    #[test]
+    fn test_replay_rolls_the_same_rounds() {
+        let mut tilemap = Tilemap::with_size(20, 1);
+        tilemap.object_groups.insert(
+            ArenaObjectGroup::SoftBlockAreas.as_str().to_string(),
+            (0..20)
+                .map(|index| map::create_soft_block_area(index, [index * 32, 0], 0.5))
+                .collect(),
+        );
+        tilemap.properties = power_ups::create_drop_properties(
+            0.5,
+            &[(PowerUpType::BombUp, 1.0), (PowerUpType::Skull, 1.0)],
+        );
+        let power_up_drops = PowerUpDrops::from_tilemap(&tilemap);
+
+        // the random parts of a round in the order in which the round rolls them
+        let roll_round = |replays: &mut Replays| {
+            let mut rng = StdRng::seed_from_u64(replays.start_round());
+            let soft_blocks = map::roll_soft_blocks(&tilemap, &mut rng)
+                .iter()
+                .map(|soft_block| soft_block.id)
+                .collect::<Vec<_>>();
+            let drops = (0..10)
+                .map(|_| power_up_drops.roll(&mut rng))
+                .collect::<Vec<_>>();
+            let diseases = (0..10)
+                .map(|_| Disease::random(&mut rng))
+                .collect::<Vec<_>>();
+
+            (soft_blocks, drops, diseases)
+        };
+
+        let replay = Replay::new(42, &PlayerSetup::new(2).unwrap(), 3, 1.0 / 120.0);
+        let mut recording = Replays::new(replay.clone(), ReplayMode::Recording(PathBuf::new()));
+        let mut playback = Replays::play(replay);
+
+        let first_round = roll_round(&mut recording);
+        assert_eq!(roll_round(&mut playback), first_round);
+        let second_round = roll_round(&mut recording);
+        assert_eq!(roll_round(&mut playback), second_round);
+        assert_ne!(first_round, second_round);
+    }
+
+    #[test]
+    fn test_failed_save_keeps_ticks_unsaved() {
+        let player_setup = PlayerSetup::new(2).unwrap();
+        // a directory can't be written like a file
+        let mut recording = Replays::new(
+            Replay::new(42, &player_setup, 3, 1.0),
+            ReplayMode::Recording(std::env::temp_dir()),
+        );
+        recording.start_round();
+
+        let command = CommandEvent::new(
+            PlayerId::Player1,
+            PlayerCommand::Bomb,
+            CommandState::Pressed,
+        );
+        for _ in 0..AUTOSAVE_INTERVAL as usize {
+            recording.add_tick(&PlayerCommands(vec![command]));
+        }
+
+        assert!(recording.has_unsaved_ticks);
+        assert!(recording.time_since_save >= AUTOSAVE_INTERVAL);
+        assert!(recording.flush().is_err());
+        assert!(recording.end_round(false).is_err());
+        assert!(recording.has_unsaved_ticks);
+    }
+}
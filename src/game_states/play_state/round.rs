@@ -0,0 +1,185 @@
+use crate::game_states::play_state::players::PlayerId;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+
+/// Time to wait after at most one player is left standing,
+/// so that players dying at almost the same time end the round in a draw.
+pub const ROUND_END_GRACE_DURATION: f64 = 1.0;
+pub const DEFAULT_BEST_OF: u32 = 3;
+pub const BEST_OF_OPTIONS: [u32; 2] = [3, 5];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundOutcome {
+    Winner(PlayerId),
+    Draw,
+}
+
+pub struct Round {
+    grace_time: Option<f64>,
+    outcome: Option<RoundOutcome>,
+}
+
+impl Round {
+    pub fn new() -> Round {
+        Round {
+            grace_time: None,
+            outcome: None,
+        }
+    }
+
+    pub fn get_outcome(&self) -> Option<RoundOutcome> {
+        self.outcome
+    }
+
+    pub fn update(&mut self, dt: f64, standing_players: &[PlayerId]) {
+        if self.outcome.is_some() || standing_players.len() > 1 {
+            return;
+        }
+
+        let grace_time = self.grace_time.get_or_insert(ROUND_END_GRACE_DURATION);
+        *grace_time -= dt;
+
+        if *grace_time <= 0.0 {
+            self.outcome = Some(match standing_players.first() {
+                Some(player_id) => RoundOutcome::Winner(*player_id),
+                None => RoundOutcome::Draw,
+            });
+        }
+    }
+}
+
+pub fn validate_best_of(best_of: u32) -> Result<u32, String> {
+    if BEST_OF_OPTIONS.contains(&best_of) {
+        Ok(best_of)
+    } else {
+        Err(format!(
+            "A match is played best of {}, but not best of {}",
+            BEST_OF_OPTIONS.iter().join(" or "),
+            best_of
+        ))
+    }
+}
+
+/// Takes `--best-of <rounds>` out of the command line and leaves the other arguments
+pub fn take_best_of_arg(args: &mut Vec<String>) -> Result<u32, String> {
+    let index = match args.iter().position(|arg| arg == "--best-of") {
+        Some(index) => index,
+        None => return Ok(DEFAULT_BEST_OF),
+    };
+
+    let best_of = args
+        .get(index + 1)
+        .and_then(|value| value.parse::<u32>().ok())
+        .ok_or_else(|| "--best-of expects a number".to_string())?;
+    args.drain(index..=index + 1);
+
+    validate_best_of(best_of)
+}
+
+/// Score of a best-of-N match, which outlives the rounds it is made of.
+pub struct MatchScore {
+    best_of: u32,
+    wins: BTreeMap<PlayerId, u32>,
+}
+
+impl MatchScore {
+    pub fn new(best_of: u32) -> MatchScore {
+        MatchScore {
+            best_of,
+            wins: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_rounds_to_win(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+
+    pub fn add_player(&mut self, player_id: PlayerId) {
+        self.wins.entry(player_id).or_insert(0);
+    }
+
+    pub fn add_round_outcome(&mut self, outcome: RoundOutcome) {
+        if let RoundOutcome::Winner(player_id) = outcome {
+            *self.wins.entry(player_id).or_insert(0) += 1;
+        }
+    }
+
+    pub fn get_wins(&self) -> Vec<(PlayerId, u32)> {
+        self.wins
+            .iter()
+            .map(|(player_id, wins)| (*player_id, *wins))
+            .collect()
+    }
+
+    pub fn get_match_winner(&self) -> Option<PlayerId> {
+        self.wins
+            .iter()
+            .find(|(_, wins)| **wins >= self.get_rounds_to_win())
+            .map(|(player_id, _)| *player_id)
+    }
+
+    pub fn reset(&mut self) {
+        self.wins.values_mut().for_each(|wins| *wins = 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_outcome() {
+        let mut round = Round::new();
+        round.update(0.5, &[PlayerId::Player1, PlayerId::Player2]);
+        assert_eq!(round.get_outcome(), None);
+
+        round.update(0.5, &[PlayerId::Player2]);
+        assert_eq!(round.get_outcome(), None);
+
+        round.update(0.25, &[]);
+        assert_eq!(round.get_outcome(), None);
+
+        round.update(0.25, &[]);
+        assert_eq!(round.get_outcome(), Some(RoundOutcome::Draw));
+
+        let mut round = Round::new();
+        round.update(ROUND_END_GRACE_DURATION, &[PlayerId::Player2]);
+        assert_eq!(
+            round.get_outcome(),
+            Some(RoundOutcome::Winner(PlayerId::Player2))
+        );
+    }
+
+    #[test]
+    fn test_match_score() {
+        let mut match_score = MatchScore::new(3);
+        match_score.add_player(PlayerId::Player1);
+        match_score.add_player(PlayerId::Player2);
+
+        match_score.add_round_outcome(RoundOutcome::Winner(PlayerId::Player2));
+        match_score.add_round_outcome(RoundOutcome::Draw);
+        match_score.add_round_outcome(RoundOutcome::Winner(PlayerId::Player1));
+        assert_eq!(match_score.get_match_winner(), None);
+
+        match_score.add_round_outcome(RoundOutcome::Winner(PlayerId::Player2));
+        assert_eq!(match_score.get_match_winner(), Some(PlayerId::Player2));
+        assert_eq!(
+            match_score.get_wins(),
+            vec![(PlayerId::Player1, 1), (PlayerId::Player2, 2)]
+        );
+    }
+
+    #[test]
+    fn test_take_best_of_arg() {
+        let mut args = vec!["--players", "3", "--best-of", "5", "--skip", "2"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(take_best_of_arg(&mut args), Ok(5));
+        assert_eq!(args, vec!["--players", "3", "--skip", "2"]);
+
+        assert_eq!(take_best_of_arg(&mut args), Ok(DEFAULT_BEST_OF));
+        assert!(take_best_of_arg(&mut vec!["--best-of".to_string(), "4".to_string()]).is_err());
+        assert!(take_best_of_arg(&mut vec!["--best-of".to_string()]).is_err());
+    }
+}
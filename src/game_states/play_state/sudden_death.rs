@@ -0,0 +1,141 @@
+use crate::tiles::tilemap::Tilemap;
+use crate::tiles::tileset::TilePosition;
+use std::collections::VecDeque;
+use tiled::PropertyValue;
+
+const DEFAULT_ROUND_DURATION: f64 = 120.0;
+const DEFAULT_FALLING_BLOCK_INTERVAL: f64 = 0.25;
+
+pub enum SuddenDeathProperties {
+    RoundDuration,
+    FallingBlockInterval,
+}
+
+impl SuddenDeathProperties {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SuddenDeathProperties::RoundDuration => "round_duration",
+            SuddenDeathProperties::FallingBlockInterval => "falling_block_interval",
+        }
+    }
+}
+
+/// Counts down the round and afterwards drops hard blocks
+/// in a spiral from the edges of the arena inward.
+pub struct SuddenDeath {
+    time_left: f64,
+    falling_block_interval: f64,
+    falling_blocks: VecDeque<TilePosition>,
+}
+
+impl SuddenDeath {
+    pub fn from_tilemap(tilemap: &Tilemap) -> SuddenDeath {
+        let get_float_property = |property: SuddenDeathProperties, default: f64| match tilemap
+            .properties
+            .get(property.as_str())
+        {
+            Some(PropertyValue::FloatValue(value)) => *value as f64,
+            _ => default,
+        };
+
+        SuddenDeath {
+            time_left: get_float_property(
+                SuddenDeathProperties::RoundDuration,
+                DEFAULT_ROUND_DURATION,
+            ),
+            falling_block_interval: get_float_property(
+                SuddenDeathProperties::FallingBlockInterval,
+                DEFAULT_FALLING_BLOCK_INTERVAL,
+            )
+            .max(0.0),
+            falling_blocks: create_spiral(tilemap.width, tilemap.height)
+                .into_iter()
+                .map(|[column, row]| [column * tilemap.tile_width, row * tilemap.tile_height])
+                .filter(|position| !tilemap.has_hit_box(*position))
+                .collect(),
+        }
+    }
+
+    /// Returns the positions of the blocks which fall during this update
+    pub fn update(&mut self, dt: f64) -> Vec<TilePosition> {
+        self.time_left -= dt;
+
+        let mut falling_blocks = vec![];
+
+        while self.time_left <= 0.0 {
+            match self.falling_blocks.pop_front() {
+                Some(position) => falling_blocks.push(position),
+                None => break,
+            }
+
+            self.time_left += self.falling_block_interval;
+
+            if self.falling_block_interval <= 0.0 {
+                break;
+            }
+        }
+
+        falling_blocks
+    }
+}
+
+/// Returns all `[column, row]` pairs of a grid clockwise from the outside in
+fn create_spiral(columns: u32, rows: u32) -> Vec<[u32; 2]> {
+    let mut spiral = Vec::with_capacity((columns * rows) as usize);
+
+    let (mut left, mut top) = (0i64, 0i64);
+    let (mut right, mut bottom) = (columns as i64 - 1, rows as i64 - 1);
+
+    while left <= right && top <= bottom {
+        (left..=right).for_each(|column| spiral.push([column, top]));
+        (top + 1..=bottom).for_each(|row| spiral.push([right, row]));
+
+        if top < bottom {
+            (left..right)
+                .rev()
+                .for_each(|column| spiral.push([column, bottom]));
+        }
+
+        if left < right {
+            (top + 1..bottom)
+                .rev()
+                .for_each(|row| spiral.push([left, row]));
+        }
+
+        left += 1;
+        top += 1;
+        right -= 1;
+        bottom -= 1;
+    }
+
+    spiral
+        .into_iter()
+        .map(|[column, row]| [column as u32, row as u32])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_spiral() {
+        assert_eq!(
+            create_spiral(4, 3),
+            vec![
+                [0, 0],
+                [1, 0],
+                [2, 0],
+                [3, 0],
+                [3, 1],
+                [3, 2],
+                [2, 2],
+                [1, 2],
+                [0, 2],
+                [0, 1],
+                [1, 1],
+                [2, 1],
+            ]
+        );
+    }
+}
@@ -1,24 +1,42 @@
+use crate::game_states::play_state::bombs;
+use crate::game_states::play_state::collisions::{self, PlayerCollisionPolicy};
 use crate::game_states::play_state::components::*;
-use crate::game_states::play_state::players::{Direction, PlayerCommand, PlayerFaceDirection};
+use crate::game_states::play_state::diseases::{self, Disease};
+use crate::game_states::play_state::grid::ArenaGrid;
+use crate::game_states::play_state::input::{CommandState, PlayerCommands};
+use crate::game_states::play_state::map::SOFT_BLOCK_BREAK_TILE_NAME;
+use crate::game_states::play_state::players::{
+    self, Direction, PlayerCommand, PlayerFaceDirection, PlayerStats, Players,
+};
+use crate::game_states::play_state::power_ups::{
+    self, PowerUpDrops, PowerUpType, POWER_UPS_TILESET_ID,
+};
+use crate::game_states::play_state::round::Round;
+use crate::game_states::play_state::sudden_death::SuddenDeath;
 use crate::game_states::play_state::PhysicsWorld;
 use crate::tiles::animation::Animation;
-use crate::tiles::tileset::TileId;
+use crate::tiles::tileset::{TileId, TilePosition};
 use crate::utils::sprite::Sprite;
 use graphics::Transformed;
 use itertools::Itertools;
 use legion::prelude::*;
-use nalgebra::Vector2;
+use nalgebra::{Isometry2, Vector2};
 use ncollide2d::narrow_phase::ContactEvent;
+use ncollide2d::query::Proximity;
 use ncollide2d::shape::{Cuboid, ShapeHandle};
-use nphysics2d::algebra::{Force2, ForceType};
 use nphysics2d::object::{Body, BodyPartHandle, BodyStatus, ColliderDesc, RigidBodyDesc};
 use opengl_graphics::{GlGraphics, Texture};
-use piston::input::{ButtonEvent, ButtonState, Event, RenderEvent, UpdateEvent};
+use piston::input::{Event, RenderEvent, UpdateEvent};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 
+fn set_tint(sprite: &mut Sprite<Texture>, tint: Option<&Tint>) {
+    let [r, g, b] = tint.map(|tint| tint.0).unwrap_or([1.0; 3]);
+    sprite.set_color(r, g, b);
+}
+
 pub fn create_draw_system(
     gl: Rc<RefCell<GlGraphics>>,
     number_of_layers: usize,
@@ -26,8 +44,25 @@ pub fn create_draw_system(
     SystemBuilder::new("draw_system")
         .read_resource::<Event>()
         .read_resource::<PhysicsWorld>()
-        .with_query(<(Read<ScreenPosition>, Read<CurrentTileId>, Read<Tileset>)>::query())
-        .with_query(<(Read<BodyHandle>, Read<CurrentTileId>, Read<Tileset>)>::query())
+        .read_component::<Rotation>()
+        .with_query(
+            <(
+                Read<ScreenPosition>,
+                Read<CurrentTileId>,
+                Read<Tileset>,
+                TryRead<Tint>,
+            )>::query()
+            .filter(!component::<Hidden>()),
+        )
+        .with_query(
+            <(
+                Read<BodyHandle>,
+                Read<CurrentTileId>,
+                Read<Tileset>,
+                TryRead<Tint>,
+            )>::query()
+            .filter(!component::<Hidden>()),
+        )
         .build_thread_local(move |_commands, world, (event, physics_world), query| {
             if let Some(render_args) = event.render_args() {
                 let graphics = &mut (*gl.borrow_mut());
@@ -42,23 +77,34 @@ pub fn create_draw_system(
                         .0
                         .clone()
                         .filter(tag_value(&layer))
-                        .iter_immutable(&*world)
-                        .for_each(|(pos, tile_id, tileset)| {
+                        .iter_entities_immutable(&*world)
+                        .for_each(|(entity, (pos, tile_id, tileset, tint))| {
                             let texture_data = tileset.0.texture_holder.get_texture_data(tile_id.0);
 
                             if let Some(texture_data) = texture_data {
+                                let [_, _, w, h] = texture_data.src_rect;
+
                                 if let Some(sprite) = &mut sprite {
                                     sprite.update_texture_data(texture_data);
                                 } else {
                                     sprite = Some(Sprite::from_texture_data(texture_data));
                                 }
 
+                                let sprite = sprite.as_mut().unwrap();
+                                set_tint(sprite, tint.as_deref());
                                 let [x, y] = pos.0;
 
-                                sprite
-                                    .as_ref()
-                                    .unwrap()
-                                    .draw(context.transform.trans(x, y), graphics)
+                                // the sprite is anchored at its top left corner
+                                let transform = match world.get_component::<Rotation>(entity) {
+                                    Some(rotation) => context
+                                        .transform
+                                        .trans(x + w / 2.0, y + h / 2.0)
+                                        .rot_deg(rotation.0)
+                                        .trans(-w / 2.0, -h / 2.0),
+                                    None => context.transform.trans(x, y),
+                                };
+
+                                sprite.draw(transform, graphics)
                             }
                         });
 
@@ -67,7 +113,7 @@ pub fn create_draw_system(
                         .clone()
                         .filter(tag_value(&layer))
                         .iter_immutable(&*world)
-                        .for_each(|(body, tile_id, tileset)| {
+                        .for_each(|(body, tile_id, tileset, tint)| {
                             let physics_world: &PhysicsWorld = &*physics_world;
                             let body = physics_world.bodies.rigid_body(body.0).unwrap();
                             let pos = body.position().translation.vector.data;
@@ -83,7 +129,10 @@ pub fn create_draw_system(
                                     sprite = Some(Sprite::from_texture_data(texture_data));
                                 }
 
-                                sprite.as_ref().unwrap().draw(
+                                let sprite = sprite.as_mut().unwrap();
+                                set_tint(sprite, tint.as_deref());
+
+                                sprite.draw(
                                     context.transform.trans(pos[0] - w / 2.0, pos[1] - h / 2.0),
                                     graphics,
                                 )
@@ -212,54 +261,65 @@ pub fn create_animation_system(
         })
 }
 
+/// Applies the command events of the current tick to the players
 pub fn create_controls_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("controls_system")
-        .read_resource::<Event>()
-        .with_query(<(Read<Controls>, Write<MoveDirectionStack>)>::query())
-        .build(move |commands, world, event, query| {
-            if let Some(button_args) = event.button_args() {
-                query.iter_entities(&mut *world).for_each(
-                    |(entity, (controls, mut move_direction_stack))| {
-                        if let Some(action) = controls.0.get(&button_args.button) {
-                            match action {
-                                PlayerCommand::Movement(direction) => match button_args.state {
-                                    ButtonState::Press => {
-                                        move_direction_stack.0.push(*direction);
-                                    }
-                                    ButtonState::Release => {
-                                        move_direction_stack
-                                            .0
-                                            .iter()
-                                            .position(|stored_direction| {
-                                                stored_direction == direction
-                                            })
-                                            .map(|index| move_direction_stack.0.remove(index));
-                                    }
-                                },
-                                PlayerCommand::Bomb => {
-                                    if button_args.state == ButtonState::Press {
-                                        commands.insert((), vec![(SpawnBomb(entity),)]);
-                                    }
+        .read_resource::<PlayerCommands>()
+        .with_query(<(
+            Write<MoveDirectionStack>,
+            Tagged<Player>,
+            TryRead<Diseased>,
+        )>::query())
+        .build(move |commands, world, player_commands, query| {
+            query.iter_entities(&mut *world).for_each(
+                |(entity, (mut move_direction_stack, player, diseased))| {
+                    let disease = diseased.map(|diseased| diseased.disease);
+
+                    for command_event in player_commands
+                        .0
+                        .iter()
+                        .filter(|command_event| command_event.player_id == player.0)
+                    {
+                        let state = command_event.state;
+                        match command_event.command {
+                            PlayerCommand::Movement(direction) => {
+                                players::update_move_direction_stack(
+                                    &mut move_direction_stack.0,
+                                    diseases::get_controlled_direction(direction, disease),
+                                    state,
+                                );
+                            }
+                            PlayerCommand::Bomb => {
+                                if state == CommandState::Pressed {
+                                    commands.insert((), vec![(SpawnBomb(entity),)]);
+                                }
+                            }
+                            PlayerCommand::Detonate => {
+                                if state == CommandState::Pressed {
+                                    commands.insert((), vec![(RemoteDetonation(entity),)]);
                                 }
                             }
                         }
-                    },
-                );
-            }
+                    }
+                },
+            );
         })
 }
 
 pub fn create_turn_player_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("turn_player_system")
-        .read_resource::<Event>()
-        .with_query(<(
-            Read<MoveDirectionStack>,
-            Read<Tileset>,
-            Write<DefaultTileId>,
-            Write<CurrentTileId>,
-        )>::query())
-        .build(move |commands, world, event, query| {
-            if let Some(_button_args) = event.button_args() {
+        .read_resource::<PlayerCommands>()
+        .with_query(
+            <(
+                Read<MoveDirectionStack>,
+                Read<Tileset>,
+                Write<DefaultTileId>,
+                Write<CurrentTileId>,
+            )>::query()
+            .filter(!component::<Dying>()),
+        )
+        .build(move |commands, world, player_commands, query| {
+            if !player_commands.0.is_empty() {
                 query.iter_entities(&mut *world).for_each(
                     |(
                         entity,
@@ -289,47 +349,179 @@ pub fn create_turn_player_system() -> Box<dyn Schedulable> {
         })
 }
 
+/// Moves players and sliding bombs to the tile under the center of their body
+pub fn create_update_grid_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("update_grid_system")
+        .read_resource::<Event>()
+        .read_resource::<Tilemap>()
+        .read_resource::<PhysicsWorld>()
+        .write_resource::<ArenaGrid>()
+        .with_query(
+            <Read<BodyHandle>>::query()
+                .filter(tag_value(&EntityType::Player) | component::<Sliding>()),
+        )
+        .build(
+            move |_commands, world, (event, tilemap, physics_world, grid), query| {
+                if let Some(_update_args) = event.update_args() {
+                    query
+                        .iter_entities_immutable(&*world)
+                        .for_each(|(entity, body_handle)| {
+                            let body = physics_world.bodies.rigid_body(body_handle.0).unwrap();
+                            let pos = body.position().translation.vector;
+
+                            if let Some(position) = tilemap.0.get_tile_position([pos.x, pos.y]) {
+                                grid.move_entity(entity, position);
+                            }
+                        });
+                }
+            },
+        )
+}
+
 pub fn create_move_player_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("move_player_system")
         .read_resource::<Event>()
-        .write_resource::<PhysicsWorld>()
-        .with_query(<(
-            Read<MoveDirectionStack>,
-            Read<MovementSpeed>,
-            Read<BodyHandle>,
-        )>::query())
-        .build(move |_commands, world, (event, physics_world), query| {
-            if let Some(_update_args) = event.update_args() {
-                query
-                    .iter(&mut *world)
-                    .for_each(|(move_direction_stack, movement_speed, body)| {
-                        if let Some(move_direction) = move_direction_stack.0.last() {
-                            let physics_world: &mut PhysicsWorld = &mut *physics_world;
+        .build(move |commands, _world, event, _query| {
+            if let Some(update_args) = event.update_args() {
+                let dt = update_args.dt;
+                commands.exec_mut(move |world| move_players(world, dt));
+            }
+        })
+}
 
-                            let move_speed = movement_speed.0;
+/// Keeps players on the lanes of the grid and slides them around corners
+/// when they are only slightly off an open lane.
+fn move_players(world: &mut World, dt: f64) {
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
 
-                            let force = match move_direction {
-                                Direction::Up => Vector2::new(0.0, -move_speed),
-                                Direction::Down => Vector2::new(0.0, move_speed),
-                                Direction::Left => Vector2::new(-move_speed, 0.0),
-                                Direction::Right => Vector2::new(move_speed, 0.0),
-                            };
+    let player_collision_policy = *world.resources.get::<PlayerCollisionPolicy>().unwrap();
 
-                            physics_world
-                                .bodies
-                                .rigid_body_mut(body.0)
-                                .unwrap()
-                                .apply_force(0, &Force2::linear(force), ForceType::Impulse, true);
-                        }
-                    })
+    // owners can walk over their bombs until they are set to static
+    let walkable_bombs: HashMap<Entity, Entity> = {
+        let physics_world = world.resources.get::<PhysicsWorld>().unwrap();
+
+        <(Read<BodyHandle>, Read<BombOwner>)>::query()
+            .iter_entities_immutable(world)
+            .filter(|(_, (body_handle, _))| {
+                physics_world
+                    .bodies
+                    .rigid_body(body_handle.0)
+                    .unwrap()
+                    .status()
+                    == BodyStatus::Disabled
+            })
+            .map(|(bomb, (_, bomb_owner))| (bomb, bomb_owner.0))
+            .collect()
+    };
+
+    let players = <(
+        Read<MoveDirectionStack>,
+        Read<MovementSpeed>,
+        Read<BodyHandle>,
+    )>::query()
+    .filter(tag_value(&EntityType::Player))
+    .iter_entities_immutable(world)
+    .map(
+        |(player, (move_direction_stack, movement_speed, body_handle))| {
+            let movement_speed = if has_disease(world, player, Disease::Slowness) {
+                diseases::SLOW_MOVEMENT_SPEED
+            } else {
+                movement_speed.0
+            };
+
+            (
+                player,
+                move_direction_stack.0.last().copied(),
+                movement_speed,
+                *body_handle,
+            )
+        },
+    )
+    .collect::<Vec<_>>();
+
+    let mut kicks = vec![];
+
+    {
+        let grid = world.resources.get::<ArenaGrid>().unwrap();
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+
+        let get_blocking_bombs = |player: Entity, position: TilePosition| {
+            grid.get_entities(position, EntityType::Bomb)
+                .filter(|bomb| walkable_bombs.get(bomb) != Some(&player))
+                .collect_vec()
+        };
+        let is_blocked = |player: Entity, position: TilePosition| {
+            tilemap.has_hit_box(position)
+                || grid.contains(position, EntityType::HardBlock)
+                || grid.contains(position, EntityType::SoftBlock)
+                || !get_blocking_bombs(player, position).is_empty()
+                || (player_collision_policy == PlayerCollisionPolicy::Block
+                    && grid
+                        .get_entities(position, EntityType::Player)
+                        .any(|other_player| other_player != player))
+        };
+
+        for (player, move_direction, movement_speed, body_handle) in players {
+            let body = physics_world.bodies.rigid_body_mut(body_handle.0).unwrap();
+            let pos = body.position().translation.vector;
+
+            let (direction, tile) =
+                match (move_direction, tilemap.get_tile_position([pos.x, pos.y])) {
+                    (Some(direction), Some(tile)) => (direction, tile),
+                    _ => {
+                        body.set_linear_velocity(Vector2::zeros());
+                        continue;
+                    }
+                };
+
+            let center = Vector2::new(
+                tile[0] as f64 + tilemap.tile_width as f64 / 2.0,
+                tile[1] as f64 + tilemap.tile_height as f64 / 2.0,
+            );
+            let tile_size = tilemap.tile_width as f64;
+
+            let movement = players::get_lane_movement(
+                pos - center,
+                direction,
+                movement_speed * tile_size,
+                tile_size,
+                dt,
+                |offset| {
+                    tilemap
+                        .get_neighbour_tile_position(tile, offset)
+                        .filter(|tile| !is_blocked(player, *tile))
+                        .is_some()
+                },
+            );
+
+            // the player has walked up to a bomb
+            if movement.is_facing_obstacle {
+                if let Some(next_tile) =
+                    tilemap.get_neighbour_tile_position(tile, direction.get_tile_offset())
+                {
+                    kicks.extend(
+                        get_blocking_bombs(player, next_tile)
+                            .into_iter()
+                            .map(|bomb| (player, bomb)),
+                    );
+                }
             }
-        })
+
+            body.set_linear_velocity(movement.velocity);
+        }
+    }
+
+    kicks
+        .into_iter()
+        .for_each(|(player, bomb)| kick_bomb(world, player, bomb));
 }
 
 pub fn create_spawn_bomb_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("spawn_bomb_system")
         .read_resource::<Event>()
         .read_component::<DeactivatedCommands>()
+        .read_component::<LineBombWindow>()
+        .read_component::<MoveDirectionStack>()
         .with_query(<Read<SpawnBomb>>::query())
         .build(move |commands, world, event, query| {
             if let Some(_update_args) = event.update_args() {
@@ -340,89 +532,461 @@ pub fn create_spawn_bomb_system() -> Box<dyn Schedulable> {
 
                         let spawner_entity = spawn_bomb.0;
 
-                        if world
-                            .get_component::<DeactivatedCommands>(spawner_entity)
-                            .unwrap()
-                            .0
-                            .contains(&PlayerCommand::Bomb)
-                        {
+                        // the second press of a double press while standing still
+                        let is_line_bomb = world
+                            .get_component::<LineBombWindow>(spawner_entity)
+                            .is_some()
+                            && world
+                                .get_component::<MoveDirectionStack>(spawner_entity)
+                                .unwrap()
+                                .0
+                                .is_empty();
+
+                        if is_line_bomb {
+                            commands.remove_component::<LineBombWindow>(spawner_entity);
+                            commands.exec_mut(move |world| drop_line_bomb(world, spawner_entity));
                             return;
                         }
 
                         commands.exec_mut(move |world| {
-                            let tile_id = 1;
-                            let tileset = world
-                                .resources
-                                .get::<AssetStorage>()
+                            // dying players can't place bombs
+                            if world.get_component::<BodyHandle>(spawner_entity).is_none() {
+                                return;
+                            }
+
+                            let position = match get_player_tile_position(world, spawner_entity) {
+                                Some(position) => position,
+                                None => return,
+                            };
+
+                            if world.get_component::<CanThrow>(spawner_entity).is_some() {
+                                let bomb = world
+                                    .resources
+                                    .get::<ArenaGrid>()
+                                    .unwrap()
+                                    .get_entities(position, EntityType::Bomb)
+                                    .next();
+
+                                if let Some(bomb) = bomb {
+                                    throw_bomb(world, spawner_entity, bomb);
+                                    return;
+                                }
+                            }
+
+                            if world
+                                .get_component::<DeactivatedCommands>(spawner_entity)
                                 .unwrap()
                                 .0
-                                .read()
-                                .unwrap()
-                                .get_asset::<crate::tiles::tileset::Tileset>("bomb");
+                                .contains(&PlayerCommand::Bomb)
+                            {
+                                return;
+                            }
 
-                            let entity = {
-                                let animation = Animation::builder(
-                                    tileset.animation_frames_holder[&tile_id].clone(),
-                                )
-                                .looping(true)
-                                .build();
-
-                                let tags = (Layer(1), EntityType::Bomb);
-                                let components = (
-                                    Tileset(tileset.clone()),
-                                    DefaultTileId(tile_id),
-                                    CurrentTileId(tile_id),
-                                    AnimationType::Ownd(animation),
+                            if world.get_component::<CanLineBomb>(spawner_entity).is_some() {
+                                world.add_component(
+                                    spawner_entity,
+                                    LineBombWindow(bombs::LINE_BOMB_DOUBLE_PRESS_DURATION),
                                 );
+                            }
 
-                                *world.insert(tags, vec![components]).first().unwrap()
-                            };
-
-                            let (body_handle, collider_handle) = {
-                                let mut physics_world =
-                                    world.resources.get_mut::<PhysicsWorld>().unwrap();
-                                let [x, y] = {
-                                    let body =
-                                        world.get_component::<BodyHandle>(spawner_entity).unwrap();
-                                    let body = physics_world.bodies.rigid_body(body.0).unwrap();
-                                    let pos = body.position().translation.vector.data;
-                                    [pos[0], pos[1]]
-                                };
-                                let [_hx, _hy, w, h] = *tileset.hit_boxes.get(&tile_id).unwrap();
-
-                                let body = RigidBodyDesc::new()
-                                    .status(BodyStatus::Disabled)
-                                    .linear_damping(5.0)
-                                    .mass(1.0)
-                                    .translation(Vector2::new(x, y))
-                                    .gravity_enabled(false)
-                                    .user_data(entity)
-                                    .build();
-                                let body_handle = physics_world.bodies.insert(body);
-
-                                let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(
-                                    Vector2::new(w / 2.0, h / 2.0),
-                                )))
-                                /*.translation(Vector2::new(
-                                    hx - half_tile_width + w / 2.0,
-                                    hy - half_tile_height + h / 2.0,
-                                ))*/
-                                .user_data(entity)
-                                .build(BodyPartHandle(body_handle, 0));
-
-                                let collider_handle = physics_world.colliders.insert(collider);
-
-                                (body_handle, collider_handle)
-                            };
-
-                            world.add_component(entity, BodyHandle(body_handle));
-                            world.add_component(entity, ColliderHandle(collider_handle));
+                            place_bomb(world, spawner_entity, position, BodyStatus::Disabled);
                         });
                     })
             }
         })
 }
 
+/// Places the bombs the player has left in a line in front of them,
+/// until the line reaches a blocked tile.
+fn drop_line_bomb(world: &mut World, player: Entity) {
+    if world.get_component::<BodyHandle>(player).is_none() {
+        return;
+    }
+
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+
+    let direction = match get_face_direction(world, player) {
+        Some(direction) => direction,
+        None => return,
+    };
+
+    let mut position = match get_player_tile_position(world, player) {
+        Some(position) => position,
+        None => return,
+    };
+
+    while let Some(next_position) =
+        tilemap.get_neighbour_tile_position(position, direction.get_tile_offset())
+    {
+        // the player isn't standing on these bombs, so they block right away
+        if !place_bomb(world, player, next_position, BodyStatus::Static) {
+            break;
+        }
+
+        position = next_position;
+    }
+}
+
+fn get_face_direction(world: &World, player: Entity) -> Option<Direction> {
+    let tileset = world.get_component::<Tileset>(player)?;
+    let tile_id = world.get_component::<DefaultTileId>(player)?;
+
+    PlayerFaceDirection::from_tile_id(&tileset.0, tile_id.0).map(Direction::from)
+}
+
+/// Lifts the bomb out of the arena and throws it into the direction the player faces.
+/// Picking the bomb up and throwing it happen on the same press, the player never carries
+/// it around, so holding a bomb can't be used to block flames or to keep its fuse paused.
+fn throw_bomb(world: &mut World, player: Entity, bomb: Entity) {
+    let direction = match get_face_direction(world, player) {
+        Some(direction) => direction,
+        None => return,
+    };
+
+    let body_handle = match world.get_component::<BodyHandle>(bomb) {
+        Some(body_handle) => *body_handle,
+        None => return,
+    };
+
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+    let tile_size = match direction {
+        Direction::Left | Direction::Right => tilemap.tile_width,
+        Direction::Up | Direction::Down => tilemap.tile_height,
+    } as f64;
+
+    // flying bombs don't collide with anything
+    world
+        .resources
+        .get_mut::<PhysicsWorld>()
+        .unwrap()
+        .bodies
+        .rigid_body_mut(body_handle.0)
+        .unwrap()
+        .set_status(BodyStatus::Disabled);
+    world.resources.get_mut::<ArenaGrid>().unwrap().remove(bomb);
+
+    world.remove_component::<Sliding>(bomb);
+    world.add_component(
+        bomb,
+        Airborne {
+            direction,
+            distance: bombs::THROW_DISTANCE as f64 * tile_size,
+            bounces: 0,
+        },
+    );
+    world.add_tag(bomb, Layer(bombs::AIRBORNE_LAYER));
+}
+
+pub fn create_fly_bomb_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("fly_bomb_system")
+        .read_resource::<Event>()
+        .with_query(<Read<Airborne>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                let dt = update_args.dt;
+
+                query
+                    .iter_entities_immutable(&*world)
+                    .for_each(|(bomb, _airborne)| {
+                        commands.exec_mut(move |world| fly_bomb(world, bomb, dt));
+                    });
+            }
+        })
+}
+
+/// Moves a thrown bomb over everything in its way. Bombs leaving the arena
+/// wrap around to the other side and bombs which would land on an occupied tile
+/// bounce on to the next one. After `bombs::MAX_THROW_BOUNCES` bounces the bomb drops
+/// onto the nearest free tile, so it never stays in the air with its fuse paused.
+fn fly_bomb(world: &mut World, bomb: Entity, dt: f64) {
+    let (airborne, body_handle) = match (
+        world.get_component::<Airborne>(bomb),
+        world.get_component::<BodyHandle>(bomb),
+    ) {
+        (Some(airborne), Some(body_handle)) => (*airborne, *body_handle),
+        _ => return,
+    };
+
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+    let [dx, dy] = airborne.direction.get_tile_offset();
+    let direction_vector = Vector2::new(dx as f64, dy as f64);
+    let tile_size = if dx != 0 {
+        tilemap.tile_width
+    } else {
+        tilemap.tile_height
+    } as f64;
+    let arena_width = (tilemap.width * tilemap.tile_width) as f64;
+    let arena_height = (tilemap.height * tilemap.tile_height) as f64;
+
+    let step = (bombs::THROW_SPEED * tile_size * dt).min(airborne.distance);
+
+    let pos = {
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+        let body = physics_world.bodies.rigid_body_mut(body_handle.0).unwrap();
+        let pos = body.position().translation.vector + direction_vector * step;
+        let pos = Vector2::new(
+            pos.x.rem_euclid(arena_width),
+            pos.y.rem_euclid(arena_height),
+        );
+
+        body.set_position(Isometry2::translation(pos.x, pos.y));
+        pos
+    };
+
+    let distance = airborne.distance - step;
+
+    if distance > 0.0 {
+        world.get_component_mut::<Airborne>(bomb).unwrap().distance = distance;
+        return;
+    }
+
+    let position = match tilemap.get_tile_position([pos.x, pos.y]) {
+        Some(position) => position,
+        None => return,
+    };
+
+    let [x, y] = if !is_tile_blocked(world, &tilemap, position, bomb) {
+        position
+    } else if airborne.bounces < bombs::MAX_THROW_BOUNCES {
+        let mut airborne = world.get_component_mut::<Airborne>(bomb).unwrap();
+        airborne.distance = tile_size;
+        airborne.bounces += 1;
+        return;
+    } else {
+        // only a completely full arena has no free tile, then the bomb lands where it is
+        bombs::find_nearest_free_position(&tilemap, position, |position| {
+            !is_tile_blocked(world, &tilemap, position, bomb)
+        })
+        .unwrap_or(position)
+    };
+
+    {
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+        let body = physics_world.bodies.rigid_body_mut(body_handle.0).unwrap();
+
+        body.set_position(Isometry2::translation(
+            x as f64 + tilemap.tile_width as f64 / 2.0,
+            y as f64 + tilemap.tile_height as f64 / 2.0,
+        ));
+        body.set_status(BodyStatus::Static);
+    }
+
+    world.remove_component::<Airborne>(bomb);
+    world.add_tag(bomb, Layer(1));
+    world
+        .resources
+        .get_mut::<ArenaGrid>()
+        .unwrap()
+        .insert(bomb, EntityType::Bomb, [x, y]);
+}
+
+/// Places a bomb of the owner on the tile, unless the owner has used up their
+/// bomb capacity or the tile is occupied. Returns whether the bomb has been placed.
+fn place_bomb(world: &mut World, owner: Entity, [x, y]: TilePosition, status: BodyStatus) -> bool {
+    if has_disease(world, owner, Disease::NoBombs) {
+        return false;
+    }
+
+    let bomb_capacity = world.get_component::<BombCapacity>(owner).unwrap().0;
+    let tileset = world
+        .resources
+        .get::<AssetStorage>()
+        .unwrap()
+        .0
+        .read()
+        .unwrap()
+        .get_asset::<crate::tiles::tileset::Tileset>("bomb");
+    let bomb_type = world.get_component::<BombVariant>(owner).unwrap().0;
+    let tile_id = match bomb_type.get_tile_id(&tileset) {
+        Some(tile_id) => tile_id,
+        None => return false,
+    };
+
+    let placed_bombs = <(Read<BombOwner>, Read<PlacementOrder>, Read<DefaultTileId>)>::query()
+        .iter_immutable(world)
+        .filter(|(bomb_owner, _, _)| bomb_owner.0 == owner)
+        .map(|(_, placement_order, tile_id)| (*placement_order, tile_id.0))
+        .collect_vec();
+
+    let uses_all_capacity =
+        |tile_id| bombs::has_property(&tileset, tile_id, bombs::BombProperties::UsesAllCapacity);
+    let is_capacity_used_up = placed_bombs.len() >= bomb_capacity as usize
+        || placed_bombs
+            .iter()
+            .any(|(_, tile_id)| uses_all_capacity(*tile_id))
+        || (!placed_bombs.is_empty() && uses_all_capacity(tile_id));
+
+    if is_capacity_used_up {
+        return false;
+    }
+
+    let placement_order = placed_bombs
+        .iter()
+        .map(|(placement_order, _)| *placement_order)
+        .max()
+        .map(|placement_order| PlacementOrder(placement_order.0 + 1))
+        .unwrap_or(PlacementOrder(0));
+
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+
+    let is_occupied = {
+        let grid = world.resources.get::<ArenaGrid>().unwrap();
+
+        tilemap.has_hit_box([x, y])
+            || [
+                EntityType::HardBlock,
+                EntityType::SoftBlock,
+                EntityType::Bomb,
+            ]
+            .iter()
+            .any(|entity_type| grid.contains([x, y], *entity_type))
+    };
+
+    if is_occupied {
+        return false;
+    }
+
+    let blast_range = if has_disease(world, owner, Disease::TinyBlastRange) {
+        diseases::TINY_BLAST_RANGE
+    } else if bombs::has_property(&tileset, tile_id, bombs::BombProperties::MaxRange) {
+        world
+            .resources
+            .get::<PlayerStats>()
+            .unwrap()
+            .blast_range
+            .max
+    } else {
+        world.get_component::<BlastRange>(owner).unwrap().0
+    };
+
+    let entity = {
+        let animation = Animation::builder(tileset.animation_frames_holder[&tile_id].clone())
+            .looping(true)
+            .build();
+
+        let tags = (Layer(1), EntityType::Bomb);
+        let components = (
+            Tileset(tileset.clone()),
+            DefaultTileId(tile_id),
+            CurrentTileId(tile_id),
+            AnimationType::Ownd(animation),
+            BlastRange(blast_range),
+            BombOwner(owner),
+            placement_order,
+        );
+
+        *world.insert(tags, vec![components]).first().unwrap()
+    };
+
+    if let Some(tint) = bombs::get_tint(&tileset, tile_id) {
+        world.add_component(entity, Tint(tint));
+    }
+
+    // remote controlled bombs only go off when the owner detonates them
+    if world.get_component::<RemoteControl>(owner).is_none() {
+        world.add_component(entity, Fuse(bombs::get_fuse_duration(&tileset, tile_id)));
+    }
+
+    let (body_handle, collider_handle) = {
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+        let [_hx, _hy, w, h] = *tileset.hit_boxes.get(&tile_id).unwrap();
+
+        let body = RigidBodyDesc::new()
+            .status(status)
+            .linear_damping(5.0)
+            .mass(1.0)
+            .translation(Vector2::new(
+                x as f64 + tilemap.tile_width as f64 / 2.0,
+                y as f64 + tilemap.tile_height as f64 / 2.0,
+            ))
+            .gravity_enabled(false)
+            .user_data(entity)
+            .build();
+        let body_handle = physics_world.bodies.insert(body);
+
+        let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(Vector2::new(
+            w / 2.0,
+            h / 2.0,
+        ))))
+        /*.translation(Vector2::new(
+            hx - half_tile_width + w / 2.0,
+            hy - half_tile_height + h / 2.0,
+        ))*/
+        .collision_groups(collisions::get_collision_groups(EntityType::Bomb))
+        .user_data(entity)
+        .build(BodyPartHandle(body_handle, 0));
+
+        let collider_handle = physics_world.colliders.insert(collider);
+
+        (body_handle, collider_handle)
+    };
+
+    world.add_component(entity, BodyHandle(body_handle));
+    world.add_component(entity, ColliderHandle(collider_handle));
+    world
+        .resources
+        .get_mut::<ArenaGrid>()
+        .unwrap()
+        .insert(entity, EntityType::Bomb, [x, y]);
+
+    true
+}
+
+pub fn create_line_bomb_window_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("line_bomb_window_system")
+        .read_resource::<Event>()
+        .with_query(<Write<LineBombWindow>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                query
+                    .iter_entities(&mut *world)
+                    .for_each(|(player, mut line_bomb_window)| {
+                        line_bomb_window.0 -= update_args.dt;
+
+                        if line_bomb_window.0 <= 0.0 {
+                            commands.remove_component::<LineBombWindow>(player);
+                        }
+                    });
+            }
+        })
+}
+
+pub fn create_remote_detonation_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("remote_detonation_system")
+        .read_resource::<Event>()
+        .read_component::<RemoteControl>()
+        .with_query(<Read<RemoteDetonation>>::query())
+        .with_query(
+            <(Read<BombOwner>, Read<PlacementOrder>)>::query()
+                .filter(!component::<Fuse>() & !component::<Airborne>()),
+        )
+        .build(move |commands, world, event, (detonations, bombs)| {
+            if let Some(_update_args) = event.update_args() {
+                detonations.iter_entities_immutable(&*world).for_each(
+                    |(entity, remote_detonation)| {
+                        commands.delete(entity);
+
+                        let player = remote_detonation.0;
+
+                        if world.get_component::<RemoteControl>(player).is_none() {
+                            return;
+                        }
+
+                        let oldest_bomb = bombs
+                            .iter_entities_immutable(&*world)
+                            .filter(|(_, (bomb_owner, _))| bomb_owner.0 == player)
+                            .min_by_key(|(_, (_, placement_order))| **placement_order)
+                            .map(|(bomb, _)| bomb);
+
+                        if let Some(bomb) = oldest_bomb {
+                            commands.insert((), vec![(Detonation(bomb),)]);
+                        }
+                    },
+                );
+            }
+        })
+}
+
 pub fn create_bomb_spawn_command_status_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("bomb_spawn_command_status_system")
         .read_resource::<Event>()
@@ -451,65 +1015,116 @@ pub fn create_bomb_spawn_command_status_system() -> Box<dyn Schedulable> {
         })
 }
 
+/// Sets bombs to static as soon as their owner has left the tile of the bomb
 pub fn create_update_bomb_collision_status_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("update_bomb_collision_status_system")
         .read_resource::<Event>()
+        .read_resource::<ArenaGrid>()
         .write_resource::<PhysicsWorld>()
-        .read_component::<BodyHandle>()
-        .with_query(
-            <(Read<Collision>, Read<BombEntity>)>::query().filter(component::<PlayerEntity>()),
+        .with_query(<(Read<BodyHandle>, Read<BombOwner>)>::query().filter(!component::<Airborne>()))
+        .build(
+            move |_commands, world, (event, grid, physics_world), query| {
+                if let Some(_update_args) = event.update_args() {
+                    query.iter_entities_immutable(&*world).for_each(
+                        |(bomb, (body_handle, bomb_owner))| {
+                            let physics_world: &mut PhysicsWorld = &mut *physics_world;
+                            let body = physics_world.bodies.rigid_body_mut(body_handle.0).unwrap();
+
+                            // kicked bombs keep sliding
+                            if body.status() != BodyStatus::Disabled {
+                                return;
+                            }
+
+                            // both are the tiles tracked by the grid, see `get_player_tile_position`
+                            let has_owner_left = grid
+                                .get_position(bomb_owner.0)
+                                .filter(|position| Some(*position) == grid.get_position(bomb))
+                                .is_none();
+
+                            if has_owner_left {
+                                body.set_status(BodyStatus::Static);
+                            }
+                        },
+                    );
+                }
+            },
         )
-        .build(move |_commands, world, (event, physics_world), query| {
-            if let Some(_update_args) = event.update_args() {
-                query.iter(&mut *world).for_each(|(collision, bomb)| {
-                    if !collision.0 {
-                        let bomb_body_handle = world.get_component::<BodyHandle>(bomb.0).unwrap();
-                        let physics_world: &mut PhysicsWorld = &mut *physics_world;
-                        physics_world
-                            .bodies
-                            .rigid_body_mut(bomb_body_handle.0)
-                            .unwrap()
-                            .set_status(BodyStatus::Static);
-                    }
-                });
-            }
-        })
 }
 
-pub fn create_collision_events_system() -> Box<dyn Schedulable> {
-    let add_colliding_entity_component =
-        |world: &mut World, entity: Entity, colliding_entity: Entity| {
-            let colliding_entity_type = world.get_tag::<EntityType>(colliding_entity).unwrap();
-
-            match colliding_entity_type {
-                EntityType::Player => world.add_component(entity, PlayerEntity(colliding_entity)),
-                EntityType::Bomb => world.add_component(entity, BombEntity(colliding_entity)),
-                EntityType::HardBlock => {
-                    world.add_component(entity, HardBlockEntity(colliding_entity))
-                }
-                EntityType::SoftBlock => {
-                    world.add_component(entity, SoftBlockEntity(colliding_entity))
-                }
-            }
+fn insert_collision_event(world: &mut World, is_colliding: bool, colliding_entities: [Entity; 2]) {
+    let entity = *world
+        .insert((), vec![(Collision(is_colliding),)])
+        .first()
+        .unwrap();
+
+    colliding_entities.iter().for_each(|&colliding_entity| {
+        // entities can be deleted before their last contact event is processed
+        let colliding_entity_type = match world.get_tag::<EntityType>(colliding_entity) {
+            Some(colliding_entity_type) => *colliding_entity_type,
+            None => return,
         };
 
+        match colliding_entity_type {
+            EntityType::Player if world.get_component::<PlayerEntity>(entity).is_some() => {
+                world.add_component(entity, OtherPlayerEntity(colliding_entity))
+            }
+            EntityType::Player => world.add_component(entity, PlayerEntity(colliding_entity)),
+            EntityType::Bomb => world.add_component(entity, BombEntity(colliding_entity)),
+            EntityType::HardBlock => world.add_component(entity, HardBlockEntity(colliding_entity)),
+            EntityType::SoftBlock => world.add_component(entity, SoftBlockEntity(colliding_entity)),
+            EntityType::Flame => {} // flames don't have colliders
+            EntityType::PowerUp => world.add_component(entity, PowerUpEntity(colliding_entity)),
+        }
+    });
+}
+
+pub fn create_collision_events_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("collision_events_system")
         .read_resource::<Event>()
         .write_resource::<PhysicsWorld>()
         .build(move |commands, _world, (event, physics_world), _query| {
             if let Some(_update_args) = event.update_args() {
                 let physics_world: &mut PhysicsWorld = &mut *physics_world;
-                physics_world
+
+                let contact_events =
+                    physics_world
+                        .geometrical_world
+                        .contact_events()
+                        .iter()
+                        .map(|contact_event| match contact_event {
+                            ContactEvent::Started(h1, h2) => (true, *h1, *h2),
+                            ContactEvent::Stopped(h1, h2) => (false, *h1, *h2),
+                        });
+
+                // sensors only report proximities
+                let proximity_events = physics_world
                     .geometrical_world
-                    .contact_events()
+                    .proximity_events()
                     .iter()
-                    .map(|contact_event| match contact_event {
-                        ContactEvent::Started(h1, h2) => (true, *h1, *h2),
-                        ContactEvent::Stopped(h1, h2) => (false, *h1, *h2),
-                    })
+                    .filter_map(|proximity_event| {
+                        let handles = (proximity_event.collider1, proximity_event.collider2);
+
+                        match (proximity_event.prev_status, proximity_event.new_status) {
+                            (_, Proximity::Intersecting) => Some((true, handles.0, handles.1)),
+                            (Proximity::Intersecting, _) => Some((false, handles.0, handles.1)),
+                            _ => None,
+                        }
+                    });
+
+                contact_events
+                    .chain(proximity_events)
                     .for_each(|(is_colliding, h1, h2)| {
-                        let collider1 = physics_world.colliders.get(h1).unwrap();
-                        let collider2 = physics_world.colliders.get(h2).unwrap();
+                        // removed colliders still report their stopped contacts
+                        let colliders = (
+                            physics_world.colliders.get(h1),
+                            physics_world.colliders.get(h2),
+                        );
+
+                        let (collider1, collider2) = match colliders {
+                            (Some(collider1), Some(collider2)) => (collider1, collider2),
+                            _ => return,
+                        };
+
                         let entities = [collider1, collider2]
                             .iter()
                             .map(|collider| {
@@ -523,19 +1138,10 @@ pub fn create_collision_events_system() -> Box<dyn Schedulable> {
 
                         if let (Some(colliding_entity1), Some(colliding_entity2)) = entities {
                             commands.exec_mut(move |world| {
-                                let entity = *world
-                                    .insert((), vec![(Collision(is_colliding),)])
-                                    .first()
-                                    .unwrap();
-
-                                [colliding_entity1, colliding_entity2].iter().for_each(
-                                    |colliding_entity| {
-                                        add_colliding_entity_component(
-                                            world,
-                                            entity,
-                                            *colliding_entity,
-                                        );
-                                    },
+                                insert_collision_event(
+                                    world,
+                                    is_colliding,
+                                    [colliding_entity1, colliding_entity2],
                                 );
                             });
                         }
@@ -558,3 +1164,1065 @@ pub fn create_clear_collision_events_system() -> Box<dyn Schedulable> {
             }
         })
 }
+
+pub fn create_fuse_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("fuse_system")
+        .read_resource::<Event>()
+        .with_query(<Write<Fuse>>::query().filter(!component::<Airborne>()))
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                query
+                    .iter_entities(&mut *world)
+                    .for_each(|(entity, mut fuse)| {
+                        if fuse.0 > 0.0 {
+                            fuse.0 -= update_args.dt;
+
+                            if fuse.0 <= 0.0 {
+                                commands.insert((), vec![(Detonation(entity),)]);
+                            }
+                        }
+                    });
+            }
+        })
+}
+
+pub fn create_detonation_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("detonation_system")
+        .read_resource::<Event>()
+        .with_query(<Read<Detonation>>::query())
+        .with_query(<Read<GridPosition>>::query().filter(tag_value(&EntityType::Flame)))
+        .build(move |commands, world, event, query| {
+            if let Some(_update_args) = event.update_args() {
+                let bombs = query
+                    .0
+                    .iter_entities_immutable(&*world)
+                    .map(|(entity, detonation)| {
+                        commands.delete(entity);
+                        detonation.0
+                    })
+                    .collect_vec();
+
+                let has_flames = query.1.iter_immutable(&*world).next().is_some();
+
+                if !bombs.is_empty() || has_flames {
+                    commands.exec_mut(move |world| {
+                        detonate_bombs(world, &bombs);
+                    });
+                }
+            }
+        })
+}
+
+/// Detonates the bombs and bombs lying in burning flames one after another
+/// in a breadth first order. Bombs hit by a flame are queued up and detonated within the same frame.
+fn detonate_bombs(world: &mut World, bombs: &[Entity]) {
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+
+    let bombs = {
+        let grid = world.resources.get::<ArenaGrid>().unwrap();
+
+        let burning_bombs = <Read<GridPosition>>::query()
+            .filter(tag_value(&EntityType::Flame))
+            .iter_immutable(world)
+            .map(|position| position.0)
+            .unique()
+            .flat_map(|position| grid.get_entities(position, EntityType::Bomb).collect_vec())
+            .collect_vec();
+
+        bombs
+            .iter()
+            .copied()
+            .chain(burning_bombs)
+            .filter_map(|bomb| grid.get_position(bomb).map(|position| (position, bomb)))
+            .collect_vec()
+    };
+
+    bombs::chain_detonations(bombs, |bomb| {
+        if !world.is_alive(bomb) {
+            return vec![];
+        }
+
+        let chained_bombs = explode_bomb(world, &tilemap, bomb);
+        delete_entity(world, bomb);
+        chained_bombs
+    });
+}
+
+/// Spawns the flames of a bomb and returns the other bombs which have been hit.
+fn explode_bomb(
+    world: &mut World,
+    tilemap: &crate::tiles::tilemap::Tilemap,
+    bomb: Entity,
+) -> Vec<Entity> {
+    let tileset = world.get_component::<Tileset>(bomb).unwrap().0.clone();
+    let blast_range = world.get_component::<BlastRange>(bomb).unwrap().0;
+    let tile_id = world.get_component::<DefaultTileId>(bomb).unwrap().0;
+    let is_piercing = bombs::has_property(&tileset, tile_id, bombs::BombProperties::Pierce);
+
+    let flames = {
+        let grid = world.resources.get::<ArenaGrid>().unwrap();
+
+        let origin = match grid.get_position(bomb) {
+            Some(origin) => origin,
+            None => return vec![],
+        };
+
+        bombs::propagate_blast(tilemap, origin, blast_range, |position| {
+            if tilemap.has_hit_box(position) || grid.contains(position, EntityType::HardBlock) {
+                Some(bombs::BlastObstacle::Indestructible)
+            } else if (grid.contains(position, EntityType::SoftBlock) && !is_piercing)
+                || grid.contains(position, EntityType::Bomb)
+                || grid.contains(position, EntityType::PowerUp)
+            {
+                Some(bombs::BlastObstacle::Destructible)
+            } else {
+                None
+            }
+        })
+    };
+
+    let mut chained_bombs = vec![];
+
+    for (position, flame_part) in flames {
+        // look up the cell before the flame and any dropped power-up are added to it
+        let (soft_blocks, power_ups, other_bombs) = {
+            let grid = world.resources.get::<ArenaGrid>().unwrap();
+            (
+                grid.get_entities(position, EntityType::SoftBlock)
+                    .collect_vec(),
+                grid.get_entities(position, EntityType::PowerUp)
+                    .collect_vec(),
+                grid.get_entities(position, EntityType::Bomb).collect_vec(),
+            )
+        };
+
+        spawn_flame(world, tilemap, &tileset, position, flame_part);
+
+        soft_blocks
+            .into_iter()
+            .for_each(|soft_block| break_soft_block(world, tilemap, soft_block, position));
+        power_ups
+            .into_iter()
+            .for_each(|power_up| delete_entity(world, power_up));
+        chained_bombs.extend(
+            other_bombs
+                .into_iter()
+                .filter(|&other_bomb| other_bomb != bomb),
+        );
+    }
+
+    chained_bombs
+}
+
+/// Replaces the soft block with its break animation, which no longer collides with anything.
+fn break_soft_block(
+    world: &mut World,
+    tilemap: &crate::tiles::tilemap::Tilemap,
+    soft_block: Entity,
+    [x, y]: TilePosition,
+) {
+    remove_physical_components(world, soft_block);
+    remove_soft_block(world, soft_block);
+
+    let power_up_type = world
+        .resources
+        .get::<PowerUpDrops>()
+        .unwrap()
+        .roll(&mut world.resources.get_mut::<SeededRng>().unwrap().0);
+
+    if let Some(power_up_type) = power_up_type {
+        spawn_power_up(world, tilemap, power_up_type, [x, y]);
+    }
+
+    let break_animation = tilemap
+        .tileset
+        .get_tile_id_by_name(SOFT_BLOCK_BREAK_TILE_NAME)
+        .and_then(|tile_id| tilemap.tileset.animation_frames_holder.get(&tile_id));
+
+    if let Some(frames) = break_animation {
+        // keep showing the last frame in case the animation finishes before the lifetime
+        let last_tile_id = frames.last().unwrap().tile_id;
+        let animation = Animation::builder(frames.clone()).build();
+
+        world.add_component(soft_block, ScreenPosition([x as f64, y as f64]));
+        world.add_component(soft_block, DefaultTileId(last_tile_id));
+        world.add_component(soft_block, CurrentTileId(animation.get_current_tile_id()));
+        world.add_component(soft_block, Lifetime(animation.get_duration()));
+        world.add_component(soft_block, AnimationType::Ownd(animation));
+    } else {
+        world.delete(soft_block);
+    }
+}
+
+fn spawn_power_up(
+    world: &mut World,
+    tilemap: &crate::tiles::tilemap::Tilemap,
+    power_up_type: PowerUpType,
+    [x, y]: TilePosition,
+) {
+    let tileset = world
+        .resources
+        .get::<AssetStorage>()
+        .unwrap()
+        .0
+        .read()
+        .unwrap()
+        .get_asset::<crate::tiles::tileset::Tileset>(POWER_UPS_TILESET_ID);
+
+    let tile_id = match power_up_type.get_tile_id(&tileset) {
+        Some(tile_id) => tile_id,
+        None => return,
+    };
+
+    let entity = {
+        let tags = (Layer(0), EntityType::PowerUp);
+        let components = (
+            PowerUp(power_up_type),
+            DefaultTileId(tile_id),
+            CurrentTileId(tile_id),
+            Tileset(tileset.clone()),
+        );
+
+        *world.insert(tags, vec![components]).first().unwrap()
+    };
+
+    let (body_handle, collider_handle) = {
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+
+        let [hx, hy, w, h] = tileset.hit_boxes[&tile_id];
+        let half_tile_width = tilemap.tile_width as f64 / 2.0;
+        let half_tile_height = tilemap.tile_height as f64 / 2.0;
+
+        let body = RigidBodyDesc::new()
+            .translation(Vector2::new(
+                x as f64 + half_tile_width,
+                y as f64 + half_tile_height,
+            ))
+            .status(BodyStatus::Static)
+            .gravity_enabled(false)
+            .user_data(entity)
+            .build();
+        let body_handle = physics_world.bodies.insert(body);
+
+        // power ups are collected by walking over them
+        let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(Vector2::new(
+            w / 2.0,
+            h / 2.0,
+        ))))
+        .translation(Vector2::new(
+            hx - half_tile_width + w / 2.0,
+            hy - half_tile_height + h / 2.0,
+        ))
+        .sensor(true)
+        .collision_groups(collisions::get_collision_groups(EntityType::PowerUp))
+        .user_data(entity)
+        .build(BodyPartHandle(body_handle, 0));
+        let collider_handle = physics_world.colliders.insert(collider);
+
+        (body_handle, collider_handle)
+    };
+
+    world.add_component(entity, BodyHandle(body_handle));
+    world.add_component(entity, ColliderHandle(collider_handle));
+    world
+        .resources
+        .get_mut::<ArenaGrid>()
+        .unwrap()
+        .insert(entity, EntityType::PowerUp, [x, y]);
+}
+
+pub fn create_pick_up_power_up_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("pick_up_power_up_system")
+        .read_resource::<Event>()
+        .with_query(<(Read<Collision>, Read<PlayerEntity>, Read<PowerUpEntity>)>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(_update_args) = event.update_args() {
+                query
+                    .iter_immutable(&*world)
+                    .for_each(|(collision, player, power_up)| {
+                        if collision.0 {
+                            let player = player.0;
+                            let power_up = power_up.0;
+
+                            commands.exec_mut(move |world| {
+                                // two players can touch the same power up at once
+                                if world.is_alive(player) && world.is_alive(power_up) {
+                                    let power_up_type =
+                                        world.get_component::<PowerUp>(power_up).unwrap().0;
+
+                                    delete_entity(world, power_up);
+                                    apply_power_up(world, player, power_up_type);
+                                }
+                            });
+                        }
+                    });
+            }
+        })
+}
+
+fn apply_power_up(world: &mut World, player: Entity, power_up_type: PowerUpType) {
+    let stats = *world.resources.get::<PlayerStats>().unwrap();
+
+    match power_up_type {
+        PowerUpType::SpeedUp | PowerUpType::SpeedDown => {
+            let mut movement_speed = world.get_component_mut::<MovementSpeed>(player).unwrap();
+
+            let step = if power_up_type == PowerUpType::SpeedUp {
+                power_ups::MOVEMENT_SPEED_STEP
+            } else {
+                -power_ups::MOVEMENT_SPEED_STEP
+            };
+
+            movement_speed.0 = stats.movement_speed.clamp(movement_speed.0 + step);
+        }
+        PowerUpType::BombUp => {
+            let mut bomb_capacity = world.get_component_mut::<BombCapacity>(player).unwrap();
+            bomb_capacity.0 = stats.bomb_capacity.clamp(bomb_capacity.0 + 1);
+        }
+        PowerUpType::BombDown => {
+            let mut bomb_capacity = world.get_component_mut::<BombCapacity>(player).unwrap();
+            bomb_capacity.0 = stats.bomb_capacity.clamp(bomb_capacity.0.saturating_sub(1));
+        }
+        PowerUpType::FireUp => {
+            let mut blast_range = world.get_component_mut::<BlastRange>(player).unwrap();
+            blast_range.0 = stats.blast_range.clamp(blast_range.0 + 1);
+        }
+        PowerUpType::FireDown => {
+            let mut blast_range = world.get_component_mut::<BlastRange>(player).unwrap();
+            blast_range.0 = stats.blast_range.clamp(blast_range.0.saturating_sub(1));
+        }
+        PowerUpType::LifeUp => {
+            let mut lives = world.get_component_mut::<Lives>(player).unwrap();
+            lives.0 = stats.lives.clamp(lives.0 + 1);
+        }
+        PowerUpType::BombKick => world.add_component(player, CanKick),
+        PowerUpType::RemoteControl => world.add_component(player, RemoteControl),
+        PowerUpType::LineBomb => world.add_component(player, CanLineBomb),
+        PowerUpType::PowerGlove => world.add_component(player, CanThrow),
+        PowerUpType::Skull => {
+            let disease = Disease::random(&mut world.resources.get_mut::<SeededRng>().unwrap().0);
+            infect_player(world, player, disease, diseases::DISEASE_DURATION)
+        }
+        PowerUpType::PierceBomb => {
+            world.get_component_mut::<BombVariant>(player).unwrap().0 = bombs::BombType::Pierce;
+        }
+        PowerUpType::PowerBomb => {
+            world.get_component_mut::<BombVariant>(player).unwrap().0 = bombs::BombType::Power;
+        }
+    }
+}
+
+fn has_disease(world: &World, player: Entity, disease: Disease) -> bool {
+    world
+        .get_component::<Diseased>(player)
+        .filter(|diseased| diseased.disease == disease)
+        .is_some()
+}
+
+/// Replaces the current disease of the player
+fn infect_player(world: &mut World, player: Entity, disease: Disease, time_left: f64) {
+    reverse_controls(world, player, Some(disease));
+    world.add_component(player, Diseased { disease, time_left });
+}
+
+fn cure_player(world: &mut World, player: Entity) {
+    reverse_controls(world, player, None);
+    world.remove_component::<Diseased>(player);
+}
+
+/// Stops the player if their next disease turns their movement directions around,
+/// see `diseases::flips_controls`
+fn reverse_controls(world: &mut World, player: Entity, next_disease: Option<Disease>) {
+    let disease = world
+        .get_component::<Diseased>(player)
+        .map(|diseased| diseased.disease);
+
+    if !diseases::flips_controls(disease, next_disease) {
+        return;
+    }
+
+    if let Some(mut move_direction_stack) = world.get_component_mut::<MoveDirectionStack>(player) {
+        move_direction_stack.0.clear();
+    }
+}
+
+pub fn create_disease_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("disease_system")
+        .read_resource::<Event>()
+        .with_query(<Write<Diseased>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                query
+                    .iter_entities(&mut *world)
+                    .for_each(|(player, mut diseased)| {
+                        diseased.time_left -= update_args.dt;
+
+                        if diseased.time_left <= 0.0 {
+                            commands.exec_mut(move |world| cure_player(world, player));
+                        } else if diseased.disease == Disease::NonstopBombs {
+                            commands.exec_mut(move |world| {
+                                if let Some(position) = get_player_tile_position(world, player) {
+                                    place_bomb(world, player, position, BodyStatus::Disabled);
+                                }
+                            });
+                        }
+                    });
+            }
+        })
+}
+
+/// Players pass their disease on to the healthy players they touch
+pub fn create_spread_disease_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("spread_disease_system")
+        .read_resource::<Event>()
+        .read_component::<Diseased>()
+        .with_query(<(
+            Read<Collision>,
+            Read<PlayerEntity>,
+            Read<OtherPlayerEntity>,
+        )>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(_update_args) = event.update_args() {
+                query
+                    .iter_immutable(&*world)
+                    .filter(|(collision, _, _)| collision.0)
+                    .for_each(|(_, player, other_player)| {
+                        let infection = match (
+                            world.get_component::<Diseased>(player.0),
+                            world.get_component::<Diseased>(other_player.0),
+                        ) {
+                            (Some(diseased), None) => Some((other_player.0, *diseased)),
+                            (None, Some(diseased)) => Some((player.0, *diseased)),
+                            _ => None,
+                        };
+
+                        if let Some((healthy_player, diseased)) = infection {
+                            commands.exec_mut(move |world| {
+                                infect_player(
+                                    world,
+                                    healthy_player,
+                                    diseased.disease,
+                                    diseased.time_left,
+                                )
+                            });
+                        }
+                    });
+            }
+        })
+}
+
+pub fn create_burn_player_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("burn_player_system")
+        .read_resource::<Event>()
+        .read_resource::<ArenaGrid>()
+        .with_query(
+            <Read<BodyHandle>>::query()
+                .filter(tag_value(&EntityType::Player) & !component::<Invulnerability>()),
+        )
+        .build(move |commands, world, (event, grid), query| {
+            if let Some(_update_args) = event.update_args() {
+                query
+                    .iter_entities_immutable(&*world)
+                    .for_each(|(player, _body)| {
+                        let is_burning = grid
+                            .get_position(player)
+                            .filter(|position| grid.contains(*position, EntityType::Flame))
+                            .is_some();
+
+                        if is_burning {
+                            commands.exec_mut(move |world| kill_player(world, player));
+                        }
+                    });
+            }
+        })
+}
+
+fn kill_player(world: &mut World, player: Entity) {
+    let screen_position = {
+        let body_handle = match world.get_component::<BodyHandle>(player) {
+            Some(body_handle) => *body_handle,
+            None => return,
+        };
+        let physics_world = world.resources.get::<PhysicsWorld>().unwrap();
+        let body = physics_world.bodies.rigid_body(body_handle.0).unwrap();
+        let pos = body.position().translation.vector.data;
+
+        let tileset = world.get_component::<Tileset>(player).unwrap();
+        let tile_id = world.get_component::<CurrentTileId>(player).unwrap().0;
+        let [_, _, w, h] = tileset
+            .0
+            .texture_holder
+            .get_texture_data(tile_id)
+            .unwrap()
+            .src_rect;
+
+        ScreenPosition([pos[0] - w / 2.0, pos[1] - h / 2.0])
+    };
+
+    remove_physical_components(world, player);
+    cure_player(world, player);
+
+    {
+        let mut lives = world.get_component_mut::<Lives>(player).unwrap();
+        lives.0 = lives.0.saturating_sub(1);
+    }
+
+    let tileset = world.get_component::<Tileset>(player).unwrap().0.clone();
+    let death_animation = tileset
+        .get_tile_id_by_name(players::DEATH_TILE_NAME)
+        .and_then(|tile_id| tileset.animation_frames_holder.get(&tile_id))
+        .map(|frames| Animation::builder(frames.clone()).build());
+
+    world.add_component(player, screen_position);
+
+    match death_animation {
+        Some(animation) => {
+            world.add_component(player, Dying(animation.get_duration()));
+            world.add_component(player, AnimationType::Ownd(animation));
+        }
+        None => world.add_component(player, Dying(0.0)),
+    }
+}
+
+pub fn create_dying_player_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("dying_player_system")
+        .read_resource::<Event>()
+        .with_query(<Write<Dying>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                query
+                    .iter_entities(&mut *world)
+                    .for_each(|(player, mut dying)| {
+                        dying.0 -= update_args.dt;
+
+                        if dying.0 <= 0.0 {
+                            commands.exec_mut(move |world| respawn_player(world, player));
+                        }
+                    });
+            }
+        })
+}
+
+/// Takes all remaining lives of a player, e.g. when they are crushed during sudden death.
+/// A player who is already dying won't respawn anymore.
+fn eliminate_player(world: &mut World, player: Entity) {
+    world.get_component_mut::<Lives>(player).unwrap().0 = 0;
+
+    if world.get_component::<Dying>(player).is_none() {
+        kill_player(world, player);
+    }
+}
+
+fn respawn_player(world: &mut World, player: Entity) {
+    let spawn_position = world.get_component::<SpawnPosition>(player).unwrap().0;
+
+    // the spawn may have collapsed during sudden death while the player was dying
+    if world
+        .resources
+        .get::<ArenaGrid>()
+        .unwrap()
+        .contains(spawn_position, EntityType::HardBlock)
+    {
+        eliminate_player(world, player);
+    }
+
+    if world.get_component::<Lives>(player).unwrap().0 == 0 {
+        // nobody is left to set off the remote controlled bombs
+        let remote_bombs = <(Read<BombOwner>, Read<Tileset>, Read<DefaultTileId>)>::query()
+            .filter(!component::<Fuse>())
+            .iter_entities_immutable(world)
+            .filter(|(_, (bomb_owner, _, _))| bomb_owner.0 == player)
+            .map(|(bomb, (_, tileset, tile_id))| {
+                (bomb, bombs::get_fuse_duration(&tileset.0, tile_id.0))
+            })
+            .collect_vec();
+
+        remote_bombs
+            .into_iter()
+            .for_each(|(bomb, fuse_duration)| world.add_component(bomb, Fuse(fuse_duration)));
+
+        world.delete(player);
+        return;
+    }
+
+    world.remove_component::<Dying>(player);
+    world.remove_component::<ScreenPosition>(player);
+
+    let tileset = world.get_component::<Tileset>(player).unwrap().0.clone();
+    let tile_id = PlayerFaceDirection::Down.get_tile_id(&tileset).unwrap();
+
+    world.add_component(player, DefaultTileId(tile_id));
+    world.add_component(player, CurrentTileId(tile_id));
+
+    if let Some(frames) = tileset.animation_frames_holder.get(&tile_id) {
+        let animation = Animation::builder(frames.clone()).looping(true).build();
+        world.add_component(player, AnimationType::Ownd(animation));
+    }
+
+    let (body_handle, collider_handle, touch_sensor_handle) = {
+        let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+        let hit_box = tileset.hit_boxes[&tile_id];
+
+        Players::create_body(
+            &mut physics_world,
+            player,
+            &tilemap,
+            spawn_position,
+            hit_box,
+        )
+    };
+
+    world.add_component(player, body_handle);
+    world.add_component(player, collider_handle);
+    world.add_component(player, touch_sensor_handle);
+    world.add_component(player, Invulnerability(players::INVULNERABILITY_DURATION));
+    world.resources.get_mut::<ArenaGrid>().unwrap().insert(
+        player,
+        EntityType::Player,
+        spawn_position,
+    );
+}
+
+pub fn create_invulnerability_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("invulnerability_system")
+        .read_resource::<Event>()
+        .with_query(<Write<Invulnerability>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                let is_hidden =
+                    |time_left: f64| (time_left / players::BLINK_INTERVAL) as u32 % 2 == 1;
+
+                query
+                    .iter_entities(&mut *world)
+                    .for_each(|(player, mut invulnerability)| {
+                        let was_hidden = is_hidden(invulnerability.0);
+                        invulnerability.0 -= update_args.dt;
+
+                        if invulnerability.0 <= 0.0 {
+                            commands.remove_component::<Invulnerability>(player);
+
+                            if was_hidden {
+                                commands.remove_component::<Hidden>(player);
+                            }
+                        } else if was_hidden != is_hidden(invulnerability.0) {
+                            if was_hidden {
+                                commands.remove_component::<Hidden>(player);
+                            } else {
+                                commands.add_component(player, Hidden);
+                            }
+                        }
+                    });
+            }
+        })
+}
+
+pub fn create_round_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("round_system")
+        .read_resource::<Event>()
+        .write_resource::<Round>()
+        .read_component::<Dying>()
+        .with_query(<Read<Lives>>::query().filter(tag_value(&EntityType::Player)))
+        .build(move |_commands, world, (event, round), query| {
+            if let Some(update_args) = event.update_args() {
+                let standing_players = query
+                    .iter_entities_immutable(&*world)
+                    .filter(|(player, lives)| {
+                        lives.0 > 0 || world.get_component::<Dying>(*player).is_none()
+                    })
+                    .map(|(player, _)| world.get_tag::<Player>(player).unwrap().0)
+                    .collect::<Vec<_>>();
+
+                round.update(update_args.dt, &standing_players);
+            }
+        })
+}
+
+fn kick_bomb(world: &mut World, player: Entity, bomb: Entity) {
+    if !world.is_alive(player)
+        || !world.is_alive(bomb)
+        || world.get_component::<CanKick>(player).is_none()
+        || world.get_component::<Sliding>(bomb).is_some()
+    {
+        return;
+    }
+
+    let direction = match world
+        .get_component::<MoveDirectionStack>(player)
+        .and_then(|move_direction_stack| move_direction_stack.0.last().copied())
+    {
+        Some(direction) => direction,
+        None => return,
+    };
+
+    let (player_body_handle, bomb_body_handle) = match (
+        world.get_component::<BodyHandle>(player),
+        world.get_component::<BodyHandle>(bomb),
+    ) {
+        (Some(player_body_handle), Some(bomb_body_handle)) => {
+            (*player_body_handle, *bomb_body_handle)
+        }
+        _ => return,
+    };
+
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+    let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+
+    let player_pos = physics_world
+        .bodies
+        .rigid_body(player_body_handle.0)
+        .unwrap()
+        .position()
+        .translation
+        .vector;
+    let bomb_body = physics_world
+        .bodies
+        .rigid_body_mut(bomb_body_handle.0)
+        .unwrap();
+
+    // the owner is still standing on the bomb
+    if bomb_body.status() != BodyStatus::Static {
+        return;
+    }
+
+    // only kick bombs the player is walking into
+    let [dx, dy] = direction.get_tile_offset();
+    let distance = bomb_body.position().translation.vector - player_pos;
+    let is_walking_into_bomb = if dx != 0 {
+        distance.x.signum() as i32 == dx && distance.x.abs() >= distance.y.abs()
+    } else {
+        distance.y.signum() as i32 == dy && distance.y.abs() >= distance.x.abs()
+    };
+
+    if !is_walking_into_bomb {
+        return;
+    }
+
+    let bomb_pos = bomb_body.position().translation.vector;
+    let [x, y] = match tilemap.get_tile_position([bomb_pos.x, bomb_pos.y]) {
+        Some(position) => position,
+        None => return,
+    };
+
+    bomb_body.set_status(BodyStatus::Kinematic);
+    bomb_body.set_position(Isometry2::translation(
+        x as f64 + tilemap.tile_width as f64 / 2.0,
+        y as f64 + tilemap.tile_height as f64 / 2.0,
+    ));
+
+    drop(physics_world);
+    world.add_component(bomb, Sliding(direction));
+}
+
+pub fn create_slide_bomb_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("slide_bomb_system")
+        .read_resource::<Event>()
+        .with_query(<Read<Sliding>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                let dt = update_args.dt;
+
+                query
+                    .iter_entities_immutable(&*world)
+                    .for_each(|(bomb, _sliding)| {
+                        commands.exec_mut(move |world| slide_bomb(world, bomb, dt));
+                    });
+            }
+        })
+}
+
+/// Moves the bomb towards the center of the next tile, but only passes
+/// the center of the current tile if the next tile is free.
+fn slide_bomb(world: &mut World, bomb: Entity, dt: f64) {
+    let (direction, body_handle) = match (
+        world.get_component::<Sliding>(bomb),
+        world.get_component::<BodyHandle>(bomb),
+    ) {
+        (Some(sliding), Some(body_handle)) => (sliding.0, *body_handle),
+        _ => return,
+    };
+
+    let tilemap = world.resources.get::<Tilemap>().unwrap().0.clone();
+    let [dx, dy] = direction.get_tile_offset();
+
+    let pos = {
+        let physics_world = world.resources.get::<PhysicsWorld>().unwrap();
+        let body = physics_world.bodies.rigid_body(body_handle.0).unwrap();
+        body.position().translation.vector
+    };
+
+    let tile = match tilemap.get_tile_position([pos.x, pos.y]) {
+        Some(tile) => tile,
+        None => return,
+    };
+
+    let center = Vector2::new(
+        tile[0] as f64 + tilemap.tile_width as f64 / 2.0,
+        tile[1] as f64 + tilemap.tile_height as f64 / 2.0,
+    );
+    let direction_vector = Vector2::new(dx as f64, dy as f64);
+    let distance_past_center = (pos - center).dot(&direction_vector);
+    let step = bombs::BOMB_SLIDE_SPEED * tilemap.tile_width as f64 * dt;
+
+    let can_pass_center = distance_past_center + step < 0.0
+        || tilemap
+            .get_neighbour_tile_position(tile, [dx, dy])
+            .filter(|next_tile| !is_tile_blocked(world, &tilemap, *next_tile, bomb))
+            .is_some();
+
+    let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+    let body = physics_world.bodies.rigid_body_mut(body_handle.0).unwrap();
+
+    if can_pass_center {
+        let new_pos = pos + direction_vector * step;
+        body.set_position(Isometry2::translation(new_pos.x, new_pos.y));
+    } else {
+        body.set_position(Isometry2::translation(center.x, center.y));
+        body.set_status(BodyStatus::Static);
+
+        drop(physics_world);
+        world.remove_component::<Sliding>(bomb);
+    }
+}
+
+fn is_tile_blocked(
+    world: &World,
+    tilemap: &crate::tiles::tilemap::Tilemap,
+    position: TilePosition,
+    ignored_entity: Entity,
+) -> bool {
+    tilemap.has_hit_box(position)
+        || [
+            EntityType::HardBlock,
+            EntityType::SoftBlock,
+            EntityType::Bomb,
+            EntityType::Player,
+            EntityType::PowerUp,
+        ]
+        .iter()
+        .any(|entity_type| {
+            world
+                .resources
+                .get::<ArenaGrid>()
+                .unwrap()
+                .get_entities(position, *entity_type)
+                .any(|entity| entity != ignored_entity)
+        })
+}
+
+pub fn create_sudden_death_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("sudden_death_system")
+        .read_resource::<Event>()
+        .write_resource::<SuddenDeath>()
+        .build(move |commands, _world, (event, sudden_death), _query| {
+            if let Some(update_args) = event.update_args() {
+                sudden_death
+                    .update(update_args.dt)
+                    .into_iter()
+                    .for_each(|position| {
+                        commands.exec_mut(move |world| drop_hard_block(world, position));
+                    });
+            }
+        })
+}
+
+fn drop_hard_block(world: &mut World, position: TilePosition) {
+    let get_entities = |world: &World, entity_type: EntityType| {
+        world
+            .resources
+            .get::<ArenaGrid>()
+            .unwrap()
+            .get_entities(position, entity_type)
+            .collect_vec()
+    };
+
+    for entity_type in &[EntityType::SoftBlock, EntityType::Bomb, EntityType::PowerUp] {
+        get_entities(world, *entity_type)
+            .into_iter()
+            .for_each(|entity| delete_entity(world, entity));
+    }
+
+    // being crushed is final, so the player can't respawn inside the collapsed arena,
+    // and neither can a dying player whose spawn collapses
+    let dying_players = <Read<SpawnPosition>>::query()
+        .filter(component::<Dying>() & tag_value(&EntityType::Player))
+        .iter_entities_immutable(world)
+        .filter(|(_, spawn_position)| spawn_position.0 == position)
+        .map(|(player, _)| player)
+        .collect_vec();
+
+    get_entities(world, EntityType::Player)
+        .into_iter()
+        .chain(dying_players)
+        .for_each(|player| eliminate_player(world, player));
+
+    let map = world.resources.get::<Map>().unwrap().0.clone();
+    map.write().unwrap().create_hard_block(world, position);
+}
+
+fn spawn_flame(
+    world: &mut World,
+    tilemap: &crate::tiles::tilemap::Tilemap,
+    tileset: &Arc<crate::tiles::tileset::Tileset>,
+    [x, y]: TilePosition,
+    flame_part: bombs::FlamePart,
+) {
+    let tile_id = match flame_part.get_tile_id(tileset) {
+        Some(tile_id) => tile_id,
+        None => return,
+    };
+
+    // flame textures are centered on their tile
+    let [offset_x, offset_y] = tileset
+        .texture_holder
+        .get_texture_data(tile_id)
+        .map(|texture_data| {
+            let [_, _, w, h] = texture_data.src_rect;
+            [
+                (tilemap.tile_width as f64 - w) / 2.0,
+                (tilemap.tile_height as f64 - h) / 2.0,
+            ]
+        })
+        .unwrap_or([0.0, 0.0]);
+
+    let tags = (Layer(1), EntityType::Flame);
+    let components = (
+        ScreenPosition([x as f64 + offset_x, y as f64 + offset_y]),
+        DefaultTileId(tile_id),
+        CurrentTileId(tile_id),
+        Tileset(tileset.clone()),
+        GridPosition([x, y]),
+        Lifetime(bombs::FLAME_DURATION),
+        Rotation(flame_part.get_rotation()),
+    );
+
+    let entity = *world.insert(tags, vec![components]).first().unwrap();
+
+    if let Some(frames) = tileset.animation_frames_holder.get(&tile_id) {
+        let animation = Animation::builder(frames.clone()).looping(true).build();
+        world.add_component(entity, AnimationType::Ownd(animation));
+    }
+
+    world
+        .resources
+        .get_mut::<ArenaGrid>()
+        .unwrap()
+        .insert(entity, EntityType::Flame, [x, y]);
+}
+
+/// The tile under the center of the player's body, which is what the grid tracks,
+/// so bombs are placed on the same tile that movement, flames and crushing use.
+/// Lane movement keeps the body centered on the tiles it walks along,
+/// so this is also the tile under the player's feet.
+fn get_player_tile_position(world: &World, player: Entity) -> Option<TilePosition> {
+    world
+        .resources
+        .get::<ArenaGrid>()
+        .unwrap()
+        .get_position(player)
+}
+
+pub fn create_lifetime_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("lifetime_system")
+        .read_resource::<Event>()
+        .with_query(<Write<Lifetime>>::query())
+        .build(move |commands, world, event, query| {
+            if let Some(update_args) = event.update_args() {
+                query
+                    .iter_entities(&mut *world)
+                    .for_each(|(entity, mut lifetime)| {
+                        if lifetime.0 > 0.0 {
+                            lifetime.0 -= update_args.dt;
+
+                            if lifetime.0 <= 0.0 {
+                                commands.exec_mut(move |world| delete_entity(world, entity));
+                            }
+                        }
+                    });
+            }
+        })
+}
+
+fn delete_entity(world: &mut World, entity: Entity) {
+    if world.is_alive(entity) {
+        remove_physical_components(world, entity);
+        if world.get_tag::<EntityType>(entity) == Some(&EntityType::SoftBlock) {
+            remove_soft_block(world, entity);
+        }
+        world.delete(entity);
+    }
+}
+
+fn remove_soft_block(world: &World, soft_block: Entity) {
+    let map = world.resources.get::<Map>().unwrap().0.clone();
+    map.write().unwrap().remove_soft_block(soft_block);
+}
+
+/// Removes the body and collider of an entity from the physics world.
+/// Contacts which are still active are reported as stopped collision events,
+/// because nphysics can't resolve the entities of removed colliders anymore.
+/// The entity doesn't take up its tile in the arena grid anymore either.
+fn remove_physical_components(world: &mut World, entity: Entity) {
+    world
+        .resources
+        .get_mut::<ArenaGrid>()
+        .unwrap()
+        .remove(entity);
+
+    let body_handle = world.get_component::<BodyHandle>(entity).map(|body| *body);
+    let collider_handle = world
+        .get_component::<ColliderHandle>(entity)
+        .map(|collider| *collider);
+    let touch_sensor_handle = world
+        .get_component::<TouchSensorHandle>(entity)
+        .map(|touch_sensor| *touch_sensor);
+
+    let touching_entities = {
+        let mut physics_world = world.resources.get_mut::<PhysicsWorld>().unwrap();
+        let physics_world: &mut PhysicsWorld = &mut physics_world;
+        let geometrical_world = &physics_world.geometrical_world;
+        let colliders = &physics_world.colliders;
+
+        let touching_entities = collider_handle
+            .and_then(|collider| geometrical_world.colliders_in_contact_with(colliders, collider.0))
+            .into_iter()
+            .flatten()
+            .chain(
+                touch_sensor_handle
+                    .and_then(|touch_sensor| {
+                        geometrical_world.colliders_in_proximity_of(colliders, touch_sensor.0)
+                    })
+                    .into_iter()
+                    .flatten(),
+            )
+            .filter_map(|(_, collider)| {
+                collider
+                    .user_data()
+                    .and_then(|it| it.downcast_ref::<Entity>())
+                    .cloned()
+            })
+            .collect_vec();
+
+        if let Some(collider) = collider_handle {
+            physics_world.colliders.remove(collider.0);
+        }
+
+        if let Some(touch_sensor) = touch_sensor_handle {
+            physics_world.colliders.remove(touch_sensor.0);
+        }
+
+        if let Some(body) = body_handle {
+            physics_world.bodies.remove(body.0);
+        }
+
+        touching_entities
+    };
+
+    touching_entities.into_iter().for_each(|touching_entity| {
+        insert_collision_event(world, false, [entity, touching_entity]);
+    });
+
+    world.remove_component::<BodyHandle>(entity);
+    world.remove_component::<ColliderHandle>(entity);
+    world.remove_component::<TouchSensorHandle>(entity);
+}
@@ -0,0 +1,171 @@
+use crate::game_states::controls_state::ControlsState;
+use crate::game_states::game_state_builder::{GameStateBuilder, GameStateBuilderBuilder};
+use crate::game_states::play_state::gamepads;
+use crate::game_states::play_state::round::{MatchScore, RoundOutcome};
+use crate::game_states::play_state::PlayState;
+use crate::game_states::state_manager::{GameState, StateTransition};
+use graphics::character::CharacterCache;
+use graphics::Transformed;
+use opengl_graphics::{GlGraphics, GlyphCache, TextureSettings};
+use piston::input::{Button, ButtonEvent, ButtonState, Event, Key, RenderEvent};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+const FONT_PATH: &str = "assets/fonts/verdana.ttf";
+const TITLE_FONT_SIZE: u32 = 32;
+const SCORE_FONT_SIZE: u32 = 18;
+const HINT_FONT_SIZE: u32 = 14;
+
+pub struct ResultsState {
+    gl: Rc<RefCell<GlGraphics>>,
+    glyph_cache: GlyphCache<'static>,
+    pending_transitions: Arc<RwLock<VecDeque<StateTransition>>>,
+    match_score: Arc<RwLock<MatchScore>>,
+    outcome: RoundOutcome,
+    /// e.g. why the replay of the round couldn't be saved
+    message: String,
+}
+
+impl ResultsState {
+    pub fn build(outcome: RoundOutcome, message: String) -> GameStateBuilder {
+        GameStateBuilderBuilder::new().build(move |resources| {
+            let results_state = ResultsState {
+                gl: resources.gl.clone(),
+                glyph_cache: GlyphCache::new(FONT_PATH, (), TextureSettings::new()).unwrap(),
+                pending_transitions: resources.pending_transitions.clone(),
+                match_score: resources.match_score.clone(),
+                outcome,
+                message,
+            };
+
+            Box::new(results_state)
+        })
+    }
+
+    fn is_match_over(&self) -> bool {
+        self.match_score
+            .read()
+            .unwrap()
+            .get_match_winner()
+            .is_some()
+    }
+
+    fn get_title(&self) -> String {
+        if let Some(player_id) = self.match_score.read().unwrap().get_match_winner() {
+            return format!("Player {} wins the match!", u32::from(player_id));
+        }
+
+        match self.outcome {
+            RoundOutcome::Winner(player_id) => format!("Player {} wins!", u32::from(player_id)),
+            RoundOutcome::Draw => "Draw!".to_string(),
+        }
+    }
+
+    fn get_score(&self) -> String {
+        self.match_score
+            .read()
+            .unwrap()
+            .get_wins()
+            .iter()
+            .map(|(player_id, wins)| format!("P{}: {}", u32::from(*player_id), wins))
+            .collect::<Vec<_>>()
+            .join("   ")
+    }
+
+    fn get_hint(&self) -> &str {
+        if self.is_match_over() {
+            "Press Enter for a new match, C for the controls or Escape to quit"
+        } else {
+            "Press Enter for the next round or C for the controls"
+        }
+    }
+
+    fn press(&mut self, key: Key) {
+        let is_match_over = self.is_match_over();
+
+        match key {
+            Key::Return => {
+                if is_match_over {
+                    self.match_score.write().unwrap().reset();
+                }
+
+                // every round is played in a freshly built arena
+                let mut pending_transitions = self.pending_transitions.write().unwrap();
+                pending_transitions.push_back(StateTransition::Clear);
+                pending_transitions.push_back(StateTransition::Push(PlayState::build()));
+            }
+            Key::C => {
+                self.pending_transitions
+                    .write()
+                    .unwrap()
+                    .push_back(StateTransition::Push(ControlsState::build(None)));
+            }
+            Key::Escape if is_match_over => {
+                self.pending_transitions
+                    .write()
+                    .unwrap()
+                    .push_back(StateTransition::Clear);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl GameState for ResultsState {
+    fn execute(&mut self, event: Event) -> bool {
+        if let Some(render_args) = event.render_args() {
+            let title = self.get_title();
+            let score = self.get_score();
+            let hint = self.get_hint().to_string();
+            let message = self.message.clone();
+
+            let graphics = &mut (*self.gl.borrow_mut());
+            let context = graphics.draw_begin(render_args.viewport());
+            let [width, height] = render_args.window_size;
+
+            graphics::rectangle(
+                [0.0, 0.0, 0.0, 0.6],
+                [0.0, 0.0, width, height],
+                context.transform,
+                graphics,
+            );
+
+            for (text, font_size, y) in &[
+                (title, TITLE_FONT_SIZE, height / 2.0),
+                (score, SCORE_FONT_SIZE, height / 2.0 + 40.0),
+                (hint, HINT_FONT_SIZE, height / 2.0 + 80.0),
+                (message, HINT_FONT_SIZE, height / 2.0 + 110.0),
+            ] {
+                let text_width = self.glyph_cache.width(*font_size, text).unwrap();
+
+                graphics::text(
+                    [1.0; 4],
+                    *font_size,
+                    text,
+                    &mut self.glyph_cache,
+                    context.transform.trans((width - text_width) / 2.0, *y),
+                    graphics,
+                )
+                .unwrap();
+            }
+
+            graphics.draw_end();
+        } else if let Some(button_args) = event.button_args() {
+            if let (ButtonState::Press, Button::Keyboard(key)) =
+                (button_args.state, button_args.button)
+            {
+                self.press(key);
+            }
+        } else if let Some(commands) = gamepads::get_commands(&event) {
+            // the transitions are applied after this event, so only the first press counts
+            if let Some(key) = commands.iter().filter_map(gamepads::get_menu_key).next() {
+                self.press(key);
+            }
+        }
+
+        // the round is over, so nothing below should receive any updates
+        false
+    }
+}
@@ -1,10 +1,15 @@
 use crate::game_states::game_state_builder::GameStateBuilder;
+use crate::game_states::play_state::controls::ControlsConfig;
+use crate::game_states::play_state::gamepads::{self, GamepadBackend, Gamepads};
+use crate::game_states::play_state::players::PlayerSetup;
+use crate::game_states::play_state::replays::Replays;
+use crate::game_states::play_state::round::MatchScore;
 use crate::utils::asset_storage::AssetStorage;
 use glutin_window::OpenGL;
 use graphics::Graphics;
 use legion::world::Universe;
 use opengl_graphics::GlGraphics;
-use piston::input::{Button, ButtonEvent, ButtonState, Event, RenderEvent};
+use piston::input::{Button, ButtonEvent, ButtonState, Event, RenderEvent, UpdateEvent};
 use std::cell::RefCell;
 use std::collections::{HashSet, VecDeque};
 use std::rc::Rc;
@@ -27,15 +32,31 @@ pub struct Resources {
     pub pending_transitions: Arc<RwLock<VecDeque<StateTransition>>>,
     pub asset_storage: Arc<RwLock<AssetStorage>>,
     pub button_storage: Arc<RwLock<HashSet<Button>>>,
+    pub match_score: Arc<RwLock<MatchScore>>,
+    pub player_setup: Arc<RwLock<PlayerSetup>>,
+    pub controls_config: Arc<RwLock<ControlsConfig>>,
+    pub replays: Arc<RwLock<Replays>>,
 }
 
 pub struct StateManager {
     stack: Vec<Box<dyn GameState>>,
     resources: Resources,
+    /// Gamepads and their player assignments outlive the states
+    gamepads: Gamepads,
+    gamepad_backend: Box<dyn GamepadBackend>,
 }
 
 impl StateManager {
-    pub fn new(game_state_builder: GameStateBuilder, opengl_version: OpenGL) -> StateManager {
+    pub fn new(
+        game_state_builder: GameStateBuilder,
+        opengl_version: OpenGL,
+        player_setup: PlayerSetup,
+        controls_config: ControlsConfig,
+        best_of: u32,
+        replays: Replays,
+        gamepad_backend: Box<dyn GamepadBackend>,
+    ) -> StateManager {
+        let gamepads = Gamepads::new(player_setup.get_player_ids());
         let mut state_manager = StateManager {
             stack: vec![],
             resources: Resources {
@@ -46,7 +67,13 @@ impl StateManager {
                 ]))),
                 asset_storage: Arc::new(RwLock::new(AssetStorage::new())),
                 button_storage: Arc::new(RwLock::new(HashSet::new())),
+                match_score: Arc::new(RwLock::new(MatchScore::new(best_of))),
+                player_setup: Arc::new(RwLock::new(player_setup)),
+                controls_config: Arc::new(RwLock::new(controls_config)),
+                replays: Arc::new(RwLock::new(replays)),
             },
+            gamepads,
+            gamepad_backend,
         };
         state_manager.apply_pending_transitions();
         state_manager
@@ -56,6 +83,11 @@ impl StateManager {
         self.stack.is_empty()
     }
 
+    /// Has to be called when the game shuts down, so the last ticks end up in the replay
+    pub fn flush_replay(&self) -> Result<(), String> {
+        self.resources.replays.write().unwrap().flush()
+    }
+
     fn apply_pending_transitions(&mut self) {
         let ref mut pending_transitions = *self.resources.pending_transitions.write().unwrap();
 
@@ -94,6 +126,17 @@ impl StateManager {
         });
     }
 
+    /// Polls the gamepads once per update, before the update itself,
+    /// so their commands count towards the same tick
+    fn poll_gamepads(&mut self) {
+        let events = self.gamepad_backend.poll();
+        let commands = self.gamepads.handle_events(events);
+
+        if !commands.is_empty() {
+            self.update(gamepads::create_event(commands));
+        }
+    }
+
     pub fn execute(&mut self, event: Event) {
         if let Some(button_args) = event.button_args() {
             match button_args.state {
@@ -126,6 +169,9 @@ impl StateManager {
         } else if event.render_args().is_some() {
             self.draw(event);
         } else {
+            if event.update_args().is_some() {
+                self.poll_gamepads();
+            }
             self.update(event);
         }
     }
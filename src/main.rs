@@ -2,22 +2,91 @@ mod game_states;
 mod tiles;
 mod utils;
 
-use crate::game_states::play_state::PlayState;
+use crate::game_states::controls_state::ControlsState;
+use crate::game_states::play_state::controls::{ControlsConfig, CONTROLS_PATH};
+use crate::game_states::play_state::gamepads::{self, NoGamepads};
+use crate::game_states::play_state::players::PlayerSetup;
+use crate::game_states::play_state::replays::{Replay, Replays};
+use crate::game_states::play_state::round;
+use crate::game_states::play_state::{self, PlayState};
 use crate::game_states::state_manager::StateManager;
 use glutin_window::{GlutinWindow, OpenGL};
 use piston::event_loop::{EventSettings, Events};
 use piston::window::WindowSettings;
 
+fn exit<T>(error: String) -> T {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
 fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // `--controls` opens the controls screen before the first round
+    let is_rebinding_controls = args
+        .iter()
+        .position(|arg| arg == "--controls")
+        .map(|index| args.remove(index))
+        .is_some();
+    let (player_setup, best_of, replays, updates_per_second) = match args.as_slice() {
+        [option, path] if option == "--replay" => {
+            let replay = Replay::load(std::path::Path::new(path)).unwrap_or_else(exit);
+            let player_setup = replay.get_player_setup().unwrap_or_else(exit);
+            let best_of = replay.best_of;
+            let updates_per_second = replay.get_updates_per_second();
+            (
+                player_setup,
+                best_of,
+                Replays::play(replay),
+                updates_per_second,
+            )
+        }
+        _ => {
+            let best_of = round::take_best_of_arg(&mut args).unwrap_or_else(exit);
+            let player_setup = PlayerSetup::from_args(args.into_iter()).unwrap_or_else(exit);
+            let updates_per_second = EventSettings::new().ups;
+            let replays = Replays::record(&player_setup, best_of, 1.0 / updates_per_second as f64);
+            (player_setup, best_of, replays, updates_per_second)
+        }
+    };
+    play_state::check_player_spawns(&player_setup).unwrap_or_else(exit);
+    // the game is still playable with the keyboard
+    let gamepad_backend = gamepads::create_backend().unwrap_or_else(|error| {
+        eprintln!("Gamepads are not available: {}", error);
+        Box::new(NoGamepads)
+    });
+    let controls_config = ControlsConfig::load(
+        std::path::Path::new(CONTROLS_PATH),
+        player_setup.get_player_ids(),
+    )
+    .unwrap_or_else(exit);
+
     let opengl_version = OpenGL::V4_5;
     let mut window: GlutinWindow = WindowSettings::new("Bomberman", [500, 500])
         .graphics_api(opengl_version)
         .build()
         .unwrap();
-    let mut events = Events::new(EventSettings::new());
-    let mut state_manager = StateManager::new(PlayState::build(), opengl_version);
+    let mut events = Events::new(EventSettings {
+        ups: updates_per_second,
+        ..EventSettings::new()
+    });
+    let game_state_builder = if is_rebinding_controls {
+        ControlsState::build(Some(PlayState::build()))
+    } else {
+        PlayState::build()
+    };
+    let mut state_manager = StateManager::new(
+        game_state_builder,
+        opengl_version,
+        player_setup,
+        controls_config,
+        best_of,
+        replays,
+        gamepad_backend,
+    );
 
     while let (Some(event), false) = (events.next(&mut window), state_manager.is_empty()) {
         state_manager.execute(event);
     }
+
+    state_manager.flush_replay().unwrap_or_else(exit);
 }
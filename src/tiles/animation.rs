@@ -43,6 +43,13 @@ impl Animation {
         self.is_finished
     }
 
+    pub fn get_duration(&self) -> f64 {
+        self.frames
+            .iter()
+            .map(|frame| frame.duration as f64 / 1000.0)
+            .sum()
+    }
+
     pub fn update(&mut self, dt: f64) {
         if self.is_paused || self.is_finished {
             return;
@@ -7,6 +7,7 @@ use std::path::Path;
 use std::sync::Arc;
 
 pub struct Tilemap {
+    pub properties: tiled::Properties,
     pub object_groups: HashMap<String, Vec<tiled::Object>>,
     pub tiles: Vec<HashMap<TilePosition, TileId>>,
     pub tileset: Arc<Tileset>,
@@ -60,6 +61,38 @@ impl Tilemap {
             .collect()
     }
 
+    pub fn get_tile_position(&self, [x, y]: [f64; 2]) -> Option<TilePosition> {
+        let column = (x / self.tile_width as f64).floor();
+        let row = (y / self.tile_height as f64).floor();
+
+        if column < 0.0 || row < 0.0 || column >= self.width as f64 || row >= self.height as f64 {
+            return None;
+        }
+
+        Some([
+            column as u32 * self.tile_width,
+            row as u32 * self.tile_height,
+        ])
+    }
+
+    pub fn get_neighbour_tile_position(
+        &self,
+        [x, y]: TilePosition,
+        [columns, rows]: [i32; 2],
+    ) -> Option<TilePosition> {
+        self.get_tile_position([
+            x as f64 + (columns * self.tile_width as i32) as f64,
+            y as f64 + (rows * self.tile_height as i32) as f64,
+        ])
+    }
+
+    pub fn has_hit_box(&self, position: TilePosition) -> bool {
+        self.tiles
+            .iter()
+            .filter_map(|layer| layer.get(&position))
+            .any(|tile_id| self.tileset.hit_boxes.contains_key(tile_id))
+    }
+
     pub fn get_used_tile_ids(&self) -> HashSet<TileId> {
         self.tiles
             .iter()
@@ -69,6 +102,23 @@ impl Tilemap {
     }
 }
 
+#[cfg(test)]
+impl Tilemap {
+    /// An empty tilemap with tiles of 32 by 32 pixels, which tests fill with what they need
+    pub fn with_size(width: u32, height: u32) -> Tilemap {
+        Tilemap {
+            properties: HashMap::new(),
+            object_groups: HashMap::new(),
+            tiles: vec![],
+            tileset: Arc::new(Tileset::default()),
+            width,
+            height,
+            tile_width: 32,
+            tile_height: 32,
+        }
+    }
+}
+
 impl Asset for Tilemap {
     fn load_from_file(path: &Path) -> Self
     where
@@ -106,6 +156,7 @@ impl Asset for Tilemap {
             });
 
         Tilemap {
+            properties: tilemap.properties.clone(),
             width: tilemap.width,
             height: tilemap.height,
             tile_width: tilemap.tile_width,
@@ -58,6 +58,16 @@ impl Tileset {
             .collect()
     }
 
+    pub fn get_tile_id_by_name(&self, name: &str) -> Option<TileId> {
+        self.properties
+            .iter()
+            .find(|(_tile_id, properties)| match properties.get("name") {
+                Some(tiled::PropertyValue::StringValue(tile_name)) => tile_name == name,
+                _ => false,
+            })
+            .map(|(tile_id, _)| *tile_id)
+    }
+
     pub fn combine(&mut self, tileset: Tileset) {
         self.texture_holder.combine(tileset.texture_holder);
         self.animation_frames_holder